@@ -15,7 +15,14 @@ typedef struct Response Response;
 typedef enum {
     WING_RESPONSE_END = 0,
     WING_RESPONSE_NODE_DEFINITION = 1,
-    WING_RESPONSE_NODE_DATA = 2
+    WING_RESPONSE_NODE_DATA = 2,
+    WING_RESPONSE_NODE_INDEX = 3,
+    WING_RESPONSE_CLICK = 4,
+    WING_RESPONSE_STEP = 5,
+    WING_RESPONSE_GOTO_ROOT = 6,
+    WING_RESPONSE_GO_UP = 7,
+    WING_RESPONSE_DATA_REQUEST = 8,
+    WING_RESPONSE_DEFINITION_REQUEST = 9
 } WingResponseType;
 
 typedef enum {
@@ -59,8 +66,11 @@ int                wing_console_request_node_data                 (WingConsole*
 void               wing_console_destroy                           (WingConsole* handle);
 
 WingResponseType   wing_response_get_type                         (const Response* handle);
+uint16_t           wing_response_get_node_index                   (const Response* handle); // for WING_RESPONSE_NODE_INDEX
+int                wing_response_get_step                         (const Response* handle); // for WING_RESPONSE_STEP
 void               wing_response_destroy                          (Response* handle);
 
+int32_t            wing_node_data_get_id                          (const Response* handle); // also the node id for click, step and request responses
 const char*        wing_node_data_get_string                      (const Response* handle); // Return value must be free by wing_string_destroy()
 float              wing_node_data_get_float                       (const Response* handle);
 int                wing_node_data_get_int                         (const Response* handle);
@@ -71,8 +81,8 @@ int                wing_node_data_has_int                         (const Respons
 int32_t            wing_node_definition_get_parent_id             (const Response* handle);
 int32_t            wing_node_definition_get_id                    (const Response* handle);
 uint16_t           wing_node_definition_get_index                 (const Response* handle);
-WingNodeType       wing_node_definition_get_type                  (const Response* handle);
-WingNodeUnit       wing_node_definition_get_unit                  (const Response* handle);
+WingNodeType       wing_node_definition_get_type                  (const Response* handle); // types newer than this header are returned as their raw code (8-15)
+WingNodeUnit       wing_node_definition_get_unit                  (const Response* handle); // units newer than this header are returned as their raw code (8-15)
 const char*        wing_node_definition_get_name                  (const Response* handle); // Return value must be free by wing_string_destroy()
 const char*        wing_node_definition_get_long_name             (const Response* handle); // Return value must be free by wing_string_destroy()
 int                wing_node_definition_is_read_only              (const Response* handle);
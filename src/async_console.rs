@@ -0,0 +1,314 @@
+use std::time::Instant;
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::{Result, Error, WingResponse};
+use crate::codec::{WingCodec, Token, Channel};
+use crate::console::{WingConsole, check_string_len, RX_BUFFER_SIZE, KEEP_ALIVE_INTERVAL, WING_PORT};
+
+/// The tokio version of `WingConsole`, enabled with the `async` feature. It decodes with the
+/// same `WingCodec`, so it returns exactly what the blocking client would.
+///
+/// Outgoing messages are queued before they are written, so a future that is dropped part way
+/// through a write never leaves half a message on the wire: the rest is sent by the next call.
+pub struct AsyncWingConsole<S = TcpStream> {
+    stream:           S,
+    codec:            WingCodec,
+    keep_alive_timer: Instant,
+    rx_buf:           [u8; RX_BUFFER_SIZE],
+    tx_queue:         Vec<u8>,
+}
+
+impl AsyncWingConsole {
+    /// Connects to `host_or_ip`, or to the first mixer found by `WingConsole::scan()` if none is
+    /// given.
+    ///
+    /// This always uses port 2222 and no timeouts; `WingConsoleBuilder` options don't apply. To
+    /// use another port or interface, connect a `TcpStream` yourself and pass it to
+    /// `from_stream()`, and wrap calls in `tokio::time::timeout()` to give up on them.
+    pub async fn connect(host_or_ip: Option<&str>) -> Result<Self> {
+        let ip =
+            if let Some(i) = host_or_ip {
+                i.to_string()
+            } else {
+                let devices = tokio::task::spawn_blocking(|| WingConsole::scan(true))
+                    .await
+                    .map_err(|_| Error::DiscoveryError)??;
+                if !devices.is_empty() {
+                    devices[0].ip.clone()
+                } else {
+                    return Err(Error::DiscoveryError);
+                }
+            };
+
+        let stream = TcpStream::connect((ip, WING_PORT)).await?;
+        stream.set_nodelay(true)?;
+        Self::from_stream(stream).await
+    }
+}
+
+impl<S: AsyncRead + AsyncWrite + Unpin> AsyncWingConsole<S> {
+    /// Runs the protocol over `stream`, which must already be connected to a mixer.
+    pub async fn from_stream(stream: S) -> Result<Self> {
+        let mut console = Self {
+            stream,
+            codec: WingCodec::new(),
+            keep_alive_timer: Instant::now(),
+            rx_buf: [0; RX_BUFFER_SIZE],
+            tx_queue: Vec::new(),
+        };
+        let mut buf = Vec::new();
+        WingCodec::encode_keep_alive(&mut buf);
+        console.write(&buf).await?;
+        Ok(console)
+    }
+
+    /// Reads the next response on `Channel::ControlEngine`, or received before the mixer selected
+    /// a channel. Anything received on other channels is queued for `read_channel()`.
+    ///
+    /// This is cancel safe: if the future is dropped, nothing that was received is lost, and
+    /// any keep alive it was sending is finished by the next call.
+    pub async fn read(&mut self) -> Result<WingResponse> {
+        self.read_channel(Channel::ControlEngine).await
+    }
+
+    /// Reads the next response on `channel`. Anything received on other channels is queued
+    /// until it is read; channels that aren't read keep only their latest 4096 tokens.
+    pub async fn read_channel(&mut self, channel: Channel) -> Result<WingResponse> {
+        loop {
+            if let Some(response) = self.codec.decode_channel(channel)? {
+                return Ok(response);
+            }
+            self.fill_rx().await?;
+        }
+    }
+
+    /// Sends `tokens` on `channel`.
+    pub async fn send(&mut self, channel: Channel, tokens: &[Token]) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_on_channel(channel, tokens, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    // Waits until something is received, sending queued bytes and keep alives meanwhile. The
+    // read and the write are polled together, so a mixer that won't take more until what it
+    // sent has been read can't leave both sides waiting on each other.
+    async fn fill_rx(&mut self) -> Result<()> {
+        loop {
+            if self.keep_alive_timer.elapsed() >= KEEP_ALIVE_INTERVAL {
+                WingCodec::encode_keep_alive(&mut self.tx_queue);
+                self.keep_alive_timer = Instant::now();
+            }
+            // wake up in time to send the next keep alive if nothing arrives
+            let wait = KEEP_ALIVE_INTERVAL.saturating_sub(self.keep_alive_timer.elapsed());
+            let (mut rx, mut tx) = tokio::io::split(&mut self.stream);
+            tokio::select! {
+                read = rx.read(&mut self.rx_buf) => match read? {
+                    0 => return Err(Error::ConnectionError),
+                    n => {
+                        self.codec.feed(&self.rx_buf[..n]);
+                        return Ok(());
+                    }
+                },
+                written = tx.write(&self.tx_queue), if !self.tx_queue.is_empty() => match written? {
+                    0 => return Err(Error::ConnectionError),
+                    n => { self.tx_queue.drain(..n); }
+                },
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+
+    // Queues `buf` behind anything not yet sent, and sends the lot.
+    async fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.tx_queue.extend_from_slice(buf);
+        self.flush().await
+    }
+
+    // Sends queued bytes, taking each out of the queue only once it has been written, so this
+    // can be dropped at any await without losing or repeating any.
+    async fn flush(&mut self) -> Result<()> {
+        while !self.tx_queue.is_empty() {
+            match self.stream.write(&self.tx_queue).await? {
+                0 => return Err(Error::ConnectionError),
+                n => { self.tx_queue.drain(..n); }
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn request_node_definition(&mut self, id: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_node_definition(id, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn request_node_data(&mut self, id: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_node_data(id, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn goto_root(&mut self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_goto_root(&mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn go_up(&mut self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_go_up(&mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn select_index(&mut self, index: u16) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_index(index, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn select_id(&mut self, id: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_id(id, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn click(&mut self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_click(&mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn step(&mut self, steps: i8) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_step(steps, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn request_current_node_data(&mut self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_current_node_data(&mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn request_current_node_definition(&mut self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_current_node_definition(&mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn set_string(&mut self, id: i32, value: &str) -> Result<()> {
+        check_string_len(id, value)?;
+        let mut buf = Vec::new();
+        WingCodec::encode_set_string(id, value, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn set_float(&mut self, id: i32, value: f32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_set_float(id, value, &mut buf)?;
+        self.write(&buf).await
+    }
+
+    pub async fn set_int(&mut self, id: i32, value: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_set_int(id, value, &mut buf)?;
+        self.write(&buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn block_on<F: std::future::Future>(future: F) -> F::Output {
+        tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap().block_on(future)
+    }
+
+    #[test]
+    fn console_over_duplex() {
+        block_on(async {
+            let (stream, mut mixer) = tokio::io::duplex(1024);
+            let mut wing = AsyncWingConsole::from_stream(stream).await.unwrap();
+            let mut handshake = [0; 2];
+            mixer.read_exact(&mut handshake).await.unwrap();
+            assert_eq!(handshake, [0xdf, 0xd1]);
+
+            mixer.write_all(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03]).await.unwrap();
+            match wing.read().await.unwrap() {
+                WingResponse::NodeData(1, 5, data) => assert_eq!(data.get_int(), 3),
+                _ => panic!("expected the data of node 5"),
+            }
+
+            wing.set_int(7, 1).await.unwrap();
+            let mut sent = [0; 6];
+            mixer.read_exact(&mut sent).await.unwrap();
+            assert_eq!(sent, [0xd7, 0, 0, 0, 7, 0x01]);
+        });
+    }
+
+    #[test]
+    fn cancelled_calls() {
+        block_on(async {
+            // room for just a few bytes, so writes block until the mixer reads
+            let (stream, mut mixer) = tokio::io::duplex(8);
+            let mut wing = AsyncWingConsole::from_stream(stream).await.unwrap();
+
+            // a read that is given up on loses nothing
+            assert!(tokio::time::timeout(Duration::from_millis(10), wing.read()).await.is_err());
+
+            // a write that is given up on part way is finished by the next call
+            let value = "x".repeat(40);
+            let mut expected = vec![0xdf, 0xd1];
+            WingCodec::encode_set_string(1234567, &value, &mut expected).unwrap();
+            assert!(tokio::time::timeout(Duration::from_millis(10), wing.set_string(1234567, &value)).await.is_err());
+
+            let len = expected.len();
+            let mixer_side = tokio::spawn(async move {
+                let mut sent = vec![0; len];
+                mixer.read_exact(&mut sent).await.unwrap();
+                mixer.write_all(&[0xdf, 0xd1, 0xde]).await.unwrap();
+                (sent, mixer)
+            });
+            assert!(matches!(wing.read().await.unwrap(), WingResponse::RequestEnd));
+            assert_eq!(mixer_side.await.unwrap().0, expected);
+        });
+    }
+    #[test]
+    fn read_while_mixer_is_sending() {
+        block_on(async {
+            let (stream, mut mixer) = tokio::io::duplex(64);
+            let mut wing = AsyncWingConsole::from_stream(stream).await.unwrap();
+
+            // a write given up on leaves more queued than the pipe holds
+            let value = "x".repeat(200);
+            let mut expected = vec![0xdf, 0xd1];
+            WingCodec::encode_set_string(1234567, &value, &mut expected).unwrap();
+            assert!(tokio::time::timeout(Duration::from_millis(10), wing.set_string(1234567, &value)).await.is_err());
+
+            // the mixer only reads once all it has to say has been read
+            let len = expected.len();
+            let mixer_side = tokio::spawn(async move {
+                let mut said = vec![0xdf, 0xd1];
+                for id in 0..100 {
+                    said.extend_from_slice(&[0xd7, 0, 0, 0, id, 0x01]);
+                }
+                mixer.write_all(&said).await.unwrap();
+                let mut sent = vec![0; len];
+                mixer.read_exact(&mut sent).await.unwrap();
+                mixer.write_all(&[0xde]).await.unwrap();
+                sent
+            });
+            for id in 0..100 {
+                let response = tokio::time::timeout(Duration::from_secs(5), wing.read()).await.unwrap().unwrap();
+                assert!(matches!(response, WingResponse::NodeData(1, i, _) if i == id));
+            }
+            let response = tokio::time::timeout(Duration::from_secs(5), wing.read()).await.unwrap().unwrap();
+            assert!(matches!(response, WingResponse::RequestEnd));
+            assert_eq!(mixer_side.await.unwrap(), expected);
+        });
+    }
+}
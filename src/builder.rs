@@ -0,0 +1,197 @@
+use std::net::{IpAddr, SocketAddr, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use socket2::{Domain, Protocol, Socket, Type};
+
+use crate::{Result, Error};
+use crate::discovery;
+use crate::transport::TcpTransport;
+use crate::console::{WingConsole, WING_PORT, KEEP_ALIVE_INTERVAL, DISCOVERY_TIMEOUT};
+
+// The shortest keep alive and ping interval taken; anything shorter would keep `read()` spinning.
+const MIN_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Options for connecting to a mixer, from `WingConsole::builder()`.
+///
+/// ```no_run
+/// # use std::time::Duration;
+/// # use libwing::WingConsole;
+/// let wing = WingConsole::builder()
+///     .host("127.0.0.1")
+///     .port(12222)
+///     .connect_timeout(Duration::from_secs(2))
+///     .connect()?;
+/// # Ok::<(), libwing::Error>(())
+/// ```
+#[derive(Clone, Debug)]
+pub struct WingConsoleBuilder {
+    host:                Option<String>,
+    port:                u16,
+    connect_timeout:     Option<Duration>,
+    read_timeout:        Option<Duration>,
+    keep_alive_interval: Duration,
+    bind_interface:      Option<IpAddr>,
+    discovery_timeout:   Duration,
+    health_check:        Option<(Duration, Duration)>,
+}
+
+impl Default for WingConsoleBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WingConsoleBuilder {
+    pub fn new() -> Self {
+        Self {
+            host:                None,
+            port:                WING_PORT,
+            connect_timeout:     None,
+            read_timeout:        None,
+            keep_alive_interval: KEEP_ALIVE_INTERVAL,
+            bind_interface:      None,
+            discovery_timeout:   DISCOVERY_TIMEOUT,
+            health_check:        None,
+        }
+    }
+
+    /// The mixer's host name or IP address. Without one, the first mixer discovered is used.
+    pub fn host(mut self, host: &str) -> Self {
+        self.host = Some(host.to_string());
+        self
+    }
+
+    /// The TCP port to connect to, 2222 by default.
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// How long to wait for the connection to be made. The default is the operating system's.
+    pub fn connect_timeout(mut self, timeout: Duration) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// How long `read()` waits before returning `Error::Timeout`. By default it waits forever.
+    pub fn read_timeout(mut self, timeout: Duration) -> Self {
+        self.read_timeout = Some(timeout);
+        self
+    }
+
+    /// How often to send a keep alive, 7 seconds by default. The mixer drops connections that
+    /// are quiet for 10 seconds. Intervals under 100ms are raised to 100ms.
+    pub fn keep_alive_interval(mut self, interval: Duration) -> Self {
+        self.keep_alive_interval = interval.max(MIN_INTERVAL);
+        self
+    }
+
+    /// The local address to connect from, to pick which network interface is used.
+    pub fn bind_interface(mut self, addr: IpAddr) -> Self {
+        self.bind_interface = Some(addr);
+        self
+    }
+
+    /// How long to look for a mixer when no host was given, 5 seconds by default.
+    pub fn discovery_timeout(mut self, timeout: Duration) -> Self {
+        self.discovery_timeout = timeout;
+        self
+    }
+
+    /// Pings the mixer every `ping_interval` while reading, to measure the round trip time
+    /// (see `WingConsole::health()`), and makes `read()` return `Error::Stalled` once nothing
+    /// has been received for `stall_timeout`. That is returned once; reading again waits for
+    /// the mixer as usual. Off by default. Both durations are raised to at least 100ms.
+    pub fn health_check(mut self, ping_interval: Duration, stall_timeout: Duration) -> Self {
+        self.health_check = Some((ping_interval.max(MIN_INTERVAL), stall_timeout.max(MIN_INTERVAL)));
+        self
+    }
+
+    pub fn connect(&self) -> Result<WingConsole> {
+        let host =
+            if let Some(host) = &self.host {
+                host.clone()
+            } else {
+                let devices = discovery::scan(true, self.discovery_timeout)?;
+                if !devices.is_empty() {
+                    devices[0].ip.clone()
+                } else {
+                    return Err(Error::DiscoveryError);
+                }
+            };
+
+        let mut last_error = None;
+        for addr in (host.as_str(), self.port).to_socket_addrs()? {
+            match self.connect_addr(addr) {
+                Ok(stream) => {
+                    return WingConsole::with_options(TcpTransport::new(stream)?, self.keep_alive_interval,
+                                                     self.read_timeout, self.health_check);
+                }
+                Err(e) => last_error = Some(e),
+            }
+        }
+        match last_error {
+            Some(e) if e.kind() == std::io::ErrorKind::TimedOut => Err(Error::Timeout),
+            Some(e) => Err(e.into()),
+            None => Err(Error::ConnectionError),
+        }
+    }
+
+    fn connect_addr(&self, addr: SocketAddr) -> std::io::Result<TcpStream> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if let Some(local) = self.bind_interface {
+            socket.bind(&SocketAddr::new(local, 0).into())?;
+        }
+        match self.connect_timeout {
+            Some(timeout) => socket.connect_timeout(&addr.into(), timeout)?,
+            None => socket.connect(&addr.into())?,
+        }
+        Ok(socket.into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn zero_intervals_are_raised() {
+        let builder = WingConsoleBuilder::new()
+            .keep_alive_interval(Duration::ZERO)
+            .health_check(Duration::ZERO, Duration::ZERO);
+        assert_eq!(builder.keep_alive_interval, MIN_INTERVAL);
+        assert_eq!(builder.health_check, Some((MIN_INTERVAL, MIN_INTERVAL)));
+
+        let builder = builder.keep_alive_interval(Duration::from_secs(3));
+        assert_eq!(builder.keep_alive_interval, Duration::from_secs(3));
+    }
+    #[test]
+    fn connect_to_listener() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let mixer = std::thread::spawn(move || {
+            use std::io::{Read, Write};
+            let (mut stream, from) = listener.accept().unwrap();
+            let mut handshake = [0; 2];
+            stream.read_exact(&mut handshake).unwrap();
+            stream.write_all(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03]).unwrap();
+            (handshake, from, stream)
+        });
+
+        let mut wing = WingConsoleBuilder::new()
+            .host("127.0.0.1")
+            .port(port)
+            .bind_interface(IpAddr::from([127, 0, 0, 1]))
+            .connect_timeout(Duration::from_secs(2))
+            .read_timeout(Duration::MAX)
+            .connect()
+            .unwrap();
+        assert!(matches!(wing.read().unwrap(), crate::WingResponse::NodeData(1, 5, _)));
+
+        let (handshake, from, _stream) = mixer.join().unwrap();
+        assert_eq!(handshake, [0xdf, 0xd1]);
+        assert_eq!(from.ip(), IpAddr::from([127, 0, 0, 1]));
+    }
+}
@@ -0,0 +1,839 @@
+use std::collections::VecDeque;
+
+use crate::{Result, Error, WingResponse};
+use crate::node::{WingNodeDef, WingNodeData};
+
+/// The longest string, in bytes, that the native protocol can carry.
+pub const MAX_STRING_LEN: usize = 256;
+
+// The longest node definition accepted. Real ones are at most a few hundred bytes, so this only
+// stops a bad length from making the decoder buffer gigabytes.
+const MAX_NODE_DEF_LEN: usize = 1 << 20;
+
+// The most tokens queued for a channel other than the control engine's, past which the oldest
+// are dropped, so a channel nobody reads can't grow without bound.
+const MAX_QUEUED_TOKENS: usize = 4096;
+
+// channel -1 (nothing selected yet) plus the 14 channels selectable with 0xdf 0xd0..0xdd
+const NUM_CHANNELS: usize = 15;
+
+/// One of the 14 channels multiplexed over a native protocol connection. Channels compare by
+/// number, so `Channel::Other(1)` is `Channel::ControlEngine`.
+#[derive(Copy, Clone, Debug)]
+pub enum Channel {
+    /// Channel 1, the node tree. This is the channel `WingConsole` uses unless told otherwise.
+    ControlEngine,
+    /// Channel 2
+    AudioEngine,
+    /// Channel 3, used to subscribe to meter data.
+    Meters,
+    /// Any other channel, 0 or 4 to 13.
+    Other(u8),
+}
+
+impl Channel {
+    /// Returns the channel with this number, or `None` if it is over 13.
+    pub fn from_id(id: u8) -> Option<Self> {
+        (id <= 13).then(|| Channel::from(id))
+    }
+
+    pub fn id(self) -> u8 {
+        match self {
+            Channel::ControlEngine => 1,
+            Channel::AudioEngine => 2,
+            Channel::Meters => 3,
+            Channel::Other(id) => id,
+        }
+    }
+}
+
+impl From<u8> for Channel {
+    /// Returns the named variant for channels 1 to 3, and `Other` for the rest. Channels over
+    /// 13 are refused when used.
+    fn from(id: u8) -> Self {
+        match id {
+            1 => Channel::ControlEngine,
+            2 => Channel::AudioEngine,
+            3 => Channel::Meters,
+            _ => Channel::Other(id),
+        }
+    }
+}
+
+impl PartialEq for Channel {
+    fn eq(&self, other: &Self) -> bool {
+        self.id() == other.id()
+    }
+}
+
+impl Eq for Channel {}
+
+impl std::hash::Hash for Channel {
+    fn hash<H: std::hash::Hasher>(&self, state: &mut H) {
+        self.id().hash(state);
+    }
+}
+
+/// A single token of the Wing native protocol, as it appears on one channel after the 0xdf
+/// escaping has been removed.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Token {
+    /// 0x00..0x3f, 0xd3 or 0xd4
+    Int(i32),
+    /// 0x40..0x7f or 0xd2: select a child of the current node by its 1-based index
+    NodeIndex(u16),
+    /// 0x80..0xbf, 0xd0 or 0xd1
+    String(String),
+    /// 0xc0..0xcf
+    NodeName(String),
+    /// 0xd5
+    Float(f32),
+    /// 0xd6
+    RawFloat(f32),
+    /// 0xd7: select a node by its id
+    NodeId(i32),
+    /// 0xd8
+    Click,
+    /// 0xd9
+    Step(i8),
+    /// 0xda
+    Root,
+    /// 0xdb
+    Up,
+    /// 0xdc
+    DataRequest,
+    /// 0xdd
+    DefinitionRequest,
+    /// 0xde
+    End,
+    /// 0xdf: the raw bytes of a node definition, see `WingNodeDef::from_bytes()`
+    NodeDef(Vec<u8>),
+}
+
+#[derive(Default)]
+struct ChannelState {
+    buf:     Vec<u8>,
+    node_id: i32,
+    // decoded tokens, each with its arrival sequence number so that decode() can hand out the
+    // tokens of all channels in the order they were received
+    tokens:  VecDeque<(u64, Result<Token>)>,
+}
+
+/// Encoder and incremental decoder for the Wing native protocol.
+///
+/// `WingCodec` does no I/O. Bytes received from the mixer are handed to `feed()` in chunks of
+/// any size, and complete tokens or responses are taken out with `next_token()` or `decode()`.
+/// The `encode_*()` functions append the bytes of a message to a buffer, ready to be written to
+/// whatever transport is connected to the mixer.
+///
+/// Each channel has its own receive queue. `decode()` and `next_token()` take from all of them
+/// in arrival order, while `decode_channel()` and `next_token_on()` take from just one, leaving
+/// the others queued. What arrives before the mixer selects a channel is read as
+/// `Channel::ControlEngine`'s. The queues of the other channels keep at most 4096 tokens,
+/// dropping the oldest, so channels that are never read don't use up memory.
+///
+/// All the `encode_*()` functions send on `Channel::ControlEngine`, except
+/// `encode_on_channel()`, which switches back to it when it is done.
+pub struct WingCodec {
+    esc:      bool,
+    channel:  i8,
+    channels: Vec<ChannelState>,
+    seq:      u64,
+}
+
+impl Default for WingCodec {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl WingCodec {
+    pub fn new() -> Self {
+        Self {
+            esc: false,
+            channel: -1,
+            channels: (0..NUM_CHANNELS).map(|_| ChannelState::default()).collect(),
+            seq: 0,
+        }
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        for &byte in data {
+            if ! self.esc {
+                if byte == 0xdf {
+                    self.esc = true;
+                } else {
+                    self.push(byte);
+                }
+            } else if byte == 0xdf {
+                self.push(0xdf);
+            } else {
+                self.esc = false;
+                if byte == 0xde {
+                    self.push(0xdf);
+                } else if (0xd0..0xde).contains(&byte) {
+                    self.channel = (byte - 0xd0) as i8;
+                } else if self.channel >= 0 {
+                    self.push(0xdf);
+                    self.push(byte);
+                } else {
+                    self.push(byte);
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, byte: u8) {
+        let state = &mut self.channels[(self.channel + 1) as usize];
+        state.buf.push(byte);
+        let token = match token_len(&state.buf) {
+            Ok(Some(len)) if len == state.buf.len() => parse_token(&state.buf),
+            Ok(_) => return,
+            Err(e) => Err(e),
+        };
+        state.buf.clear();
+        if let Some(token) = token.transpose() {
+            state.tokens.push_back((self.seq, token));
+            self.seq += 1;
+            if self.channel > 1 || self.channel == 0 {
+                while state.tokens.len() > MAX_QUEUED_TOKENS {
+                    state.tokens.pop_front();
+                }
+            }
+        }
+    }
+
+    fn pop_token(&mut self, ch: i8) -> Result<Option<(i8, Token)>> {
+        match self.channels[(ch + 1) as usize].tokens.pop_front() {
+            Some((_, token)) => Ok(Some((ch, token?))),
+            None => Ok(None),
+        }
+    }
+
+    // the channel holding the oldest undecoded token, of those read by `channel`, or of all
+    fn oldest_channel(&self, channel: Option<i8>) -> Option<i8> {
+        self.channels.iter()
+            .enumerate()
+            .map(|(i, state)| (i as i8 - 1, state))
+            .filter(|(ch, _)| match channel {
+                // the control engine's reads also take what came before any channel was selected
+                Some(1) => *ch == 1 || *ch == -1,
+                Some(channel) => *ch == channel,
+                None => true,
+            })
+            .filter_map(|(ch, state)| state.tokens.front().map(|(seq, _)| (*seq, ch)))
+            .min()
+            .map(|(_, ch)| ch)
+    }
+
+    /// Returns the next complete token on any channel and the channel it arrived on, or `None`
+    /// if more bytes need to be fed first. The channel is -1 for bytes received before the
+    /// mixer selected a channel.
+    pub fn next_token(&mut self) -> Result<Option<(i8, Token)>> {
+        match self.oldest_channel(None) {
+            Some(ch) => self.pop_token(ch),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the next complete token received on `channel`, or `None` if there isn't one yet.
+    pub fn next_token_on(&mut self, channel: Channel) -> Result<Option<Token>> {
+        match self.oldest_channel(Some(channel_number(channel)?)) {
+            Some(ch) => Ok(self.pop_token(ch)?.map(|(_, token)| token)),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the next complete response on any channel, or `None` if more bytes need to be
+    /// fed first.
+    pub fn decode(&mut self) -> Result<Option<WingResponse>> {
+        while let Some(ch) = self.oldest_channel(None) {
+            if let Some(response) = self.decode_from(ch)? {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Returns the next complete response received on `channel`, or `None` if there isn't one
+    /// yet. Responses on other channels stay queued.
+    pub fn decode_channel(&mut self, channel: Channel) -> Result<Option<WingResponse>> {
+        let channel = channel_number(channel)?;
+        while let Some(ch) = self.oldest_channel(Some(channel)) {
+            if let Some(response) = self.decode_from(ch)? {
+                return Ok(Some(response));
+            }
+        }
+        Ok(None)
+    }
+
+    // Decodes the next token of channel ch, if it has one. Tokens that only update the
+    // decoder's state give None.
+    fn decode_from(&mut self, ch: i8) -> Result<Option<WingResponse>> {
+        let Some((ch, token)) = self.pop_token(ch)? else { return Ok(None) };
+        let node_id = &mut self.channels[(ch + 1) as usize].node_id;
+        let data = match token {
+            Token::Int(v) => WingNodeData::with_i32(v),
+            Token::String(v) | Token::NodeName(v) => WingNodeData::with_string(v),
+            Token::Float(v) | Token::RawFloat(v) => WingNodeData::with_float(v),
+            Token::NodeId(id) => { *node_id = id; return Ok(None); }
+            Token::NodeIndex(v) => return Ok(Some(WingResponse::NodeIndex(v))),
+            Token::Click => return Ok(Some(WingResponse::Click(*node_id))),
+            Token::Step(v) => return Ok(Some(WingResponse::Step(*node_id, v))),
+            Token::Root => { *node_id = 0; return Ok(Some(WingResponse::GotoRoot)); }
+            Token::Up => return Ok(Some(WingResponse::GoUp)),
+            Token::DataRequest => return Ok(Some(WingResponse::DataRequest(*node_id))),
+            Token::DefinitionRequest => return Ok(Some(WingResponse::DefinitionRequest(*node_id))),
+            Token::End => return Ok(Some(WingResponse::RequestEnd)),
+            Token::NodeDef(raw) => return Ok(Some(WingResponse::NodeDef(WingNodeDef::from_bytes(&raw)?))),
+        };
+        Ok(Some(WingResponse::NodeData(ch, *node_id, data)))
+    }
+
+    pub fn encode_keep_alive(buf: &mut Vec<u8>) {
+        Self::encode_select_channel(Channel::ControlEngine, buf);
+    }
+
+    fn encode_select_channel(channel: Channel, buf: &mut Vec<u8>) {
+        buf.push(0xdf);
+        buf.push(0xd0 + channel.id());
+    }
+
+    /// Appends `tokens` to `buf` to be sent on `channel`, then switches back to
+    /// `Channel::ControlEngine`.
+    pub fn encode_on_channel(channel: Channel, tokens: &[Token], buf: &mut Vec<u8>) -> Result<()> {
+        channel_number(channel)?;
+        if channel == Channel::ControlEngine {
+            return Self::encode_tokens(tokens, buf);
+        }
+        Self::encode_select_channel(channel, buf);
+        Self::encode_tokens(tokens, buf)?;
+        Self::encode_select_channel(Channel::ControlEngine, buf);
+        Ok(())
+    }
+
+    /// Appends one token to `buf`. Every byte goes through the 0xdf escaping, so this is safe
+    /// for any payload value.
+    pub fn encode_token(token: &Token, buf: &mut Vec<u8>) -> Result<()> {
+        match token {
+            Token::Int(v) => {
+                if (0..=0x3f).contains(v) {
+                    put(buf, &[*v as u8]);
+                } else if let Ok(v) = i16::try_from(*v) {
+                    put(buf, &[0xd3]);
+                    put(buf, &v.to_be_bytes());
+                } else {
+                    put(buf, &[0xd4]);
+                    put(buf, &v.to_be_bytes());
+                }
+            }
+            Token::NodeIndex(index) => {
+                if *index == 0 {
+                    return Err(Error::InvalidNodeIndex);
+                } else if *index <= 64 {
+                    put(buf, &[0x40 + (index - 1) as u8]);
+                } else {
+                    put(buf, &[0xd2]);
+                    put(buf, &(index - 1).to_be_bytes());
+                }
+            }
+            Token::String(value) => {
+                if value.is_empty() {
+                    put(buf, &[0xd0]);
+                } else if value.len() <= 64 {
+                    put(buf, &[0x7f + value.len() as u8]);
+                } else if value.len() <= MAX_STRING_LEN {
+                    put(buf, &[0xd1, (value.len()-1) as u8]);
+                } else {
+                    return Err(Error::StringTooLong(value.len(), MAX_STRING_LEN));
+                }
+                put(buf, value.as_bytes());
+            }
+            Token::NodeName(name) => {
+                if name.is_empty() {
+                    return Err(Error::EmptyNodeName);
+                } else if name.len() > 16 {
+                    return Err(Error::StringTooLong(name.len(), 16));
+                }
+                put(buf, &[0xbf + name.len() as u8]);
+                put(buf, name.as_bytes());
+            }
+            Token::Float(v) => {
+                put(buf, &[0xd5]);
+                put(buf, &v.to_be_bytes());
+            }
+            Token::RawFloat(v) => {
+                put(buf, &[0xd6]);
+                put(buf, &v.to_be_bytes());
+            }
+            Token::NodeId(id) => {
+                put(buf, &[0xd7]);
+                put(buf, &id.to_be_bytes());
+            }
+            Token::Click => put(buf, &[0xd8]),
+            Token::Step(steps) => put(buf, &[0xd9, *steps as u8]),
+            Token::Root => put(buf, &[0xda]),
+            Token::Up => put(buf, &[0xdb]),
+            Token::DataRequest => put(buf, &[0xdc]),
+            Token::DefinitionRequest => put(buf, &[0xdd]),
+            Token::End => put(buf, &[0xde]),
+            Token::NodeDef(raw) => {
+                if raw.len() > MAX_NODE_DEF_LEN {
+                    return Err(Error::NodeDefEncode("definition too long"));
+                }
+                put(buf, &[0xdf]);
+                match u16::try_from(raw.len()) {
+                    Ok(len) if len != 0 => put(buf, &len.to_be_bytes()),
+                    _ => {
+                        put(buf, &0u16.to_be_bytes());
+                        put(buf, &(raw.len() as u32).to_be_bytes());
+                    }
+                }
+                put(buf, raw);
+            }
+        }
+        Ok(())
+    }
+
+    /// Appends `bytes` to `buf` to be sent as they are (but escaped) on `channel`, then switches
+    /// back to `Channel::ControlEngine`. This is for channels that don't use the node tree's
+    /// tokens, such as `Channel::Meters`.
+    pub fn encode_raw_on_channel(channel: Channel, bytes: &[u8], buf: &mut Vec<u8>) -> Result<()> {
+        channel_number(channel)?;
+        Self::encode_select_channel(channel, buf);
+        put(buf, bytes);
+        Self::encode_select_channel(Channel::ControlEngine, buf);
+        Ok(())
+    }
+
+    fn encode_tokens(tokens: &[Token], buf: &mut Vec<u8>) -> Result<()> {
+        for token in tokens {
+            Self::encode_token(token, buf)?;
+        }
+        Ok(())
+    }
+
+    fn select(id: i32) -> Token {
+        if id == 0 { Token::Root } else { Token::NodeId(id) }
+    }
+
+    pub fn encode_request_node_definition(id: i32, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_tokens(&[Self::select(id), Token::DefinitionRequest], buf)
+    }
+
+    pub fn encode_request_node_data(id: i32, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_tokens(&[Self::select(id), Token::DataRequest], buf)
+    }
+
+    pub fn encode_goto_root(buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::Root, buf)
+    }
+
+    pub fn encode_go_up(buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::Up, buf)
+    }
+
+    /// Selects the child of the current node with the given 1-based index.
+    pub fn encode_select_index(index: u16, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::NodeIndex(index), buf)
+    }
+
+    pub fn encode_select_id(id: i32, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::NodeId(id), buf)
+    }
+
+    pub fn encode_click(buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::Click, buf)
+    }
+
+    pub fn encode_step(steps: i8, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::Step(steps), buf)
+    }
+
+    pub fn encode_request_current_node_data(buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::DataRequest, buf)
+    }
+
+    pub fn encode_request_current_node_definition(buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_token(&Token::DefinitionRequest, buf)
+    }
+
+    /// Fails with `Error::StringTooLong` if `value` is longer than `MAX_STRING_LEN` bytes.
+    pub fn encode_set_string(id: i32, value: &str, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_tokens(&[Token::NodeId(id), Token::String(value.to_string())], buf)
+    }
+
+    pub fn encode_set_float(id: i32, value: f32, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_tokens(&[Token::NodeId(id), Token::Float(value)], buf)
+    }
+
+    pub fn encode_set_int(id: i32, value: i32, buf: &mut Vec<u8>) -> Result<()> {
+        Self::encode_tokens(&[Token::NodeId(id), Token::Int(value)], buf)
+    }
+}
+
+fn channel_number(channel: Channel) -> Result<i8> {
+    match channel.id() {
+        id @ 0..=13 => Ok(id as i8),
+        id => Err(Error::InvalidChannel(id)),
+    }
+}
+
+// The one place outgoing bytes are written: 0xdf is sent as 0xdf 0xde so it can't be mistaken
+// for the start of an escape sequence.
+fn put(buf: &mut Vec<u8>, bytes: &[u8]) {
+    for &b in bytes {
+        buf.push(b);
+        if b == 0xdf { buf.push(0xde); }
+    }
+}
+
+// Number of unescaped bytes the token starting at buf[0] occupies, or None if more bytes are
+// needed before that can be known. Fails with `Error::InvalidData` for a node definition longer
+// than `MAX_NODE_DEF_LEN`.
+fn token_len(buf: &[u8]) -> Result<Option<usize>> {
+    let cmd = buf[0];
+    let len = match cmd {
+        0x00..=0x7f => Some(1),
+        0x80..=0xbf => Some(1 + (cmd - 0x80) as usize + 1),
+        0xc0..=0xcf => Some(1 + (cmd - 0xc0) as usize + 1),
+        0xd0 => Some(1),
+        0xd1 => buf.get(1).map(|len| 2 + *len as usize + 1),
+        0xd2 | 0xd3 => Some(3),
+        0xd4..=0xd7 => Some(5),
+        0xd8 => Some(1),
+        0xd9 => Some(2),
+        0xda..=0xde => Some(1),
+        0xdf => {
+            // a 16 bit length of zero means a 32 bit length follows
+            if buf.len() < 3 {
+                None
+            } else {
+                let def_len = u16::from_be_bytes([buf[1], buf[2]]) as usize;
+                if def_len != 0 {
+                    Some(3 + def_len)
+                } else if buf.len() < 7 {
+                    None
+                } else {
+                    let def_len = usize::try_from(u32::from_be_bytes([buf[3], buf[4], buf[5], buf[6]]))
+                        .ok()
+                        .filter(|len| *len <= MAX_NODE_DEF_LEN)
+                        .ok_or(Error::InvalidData)?;
+                    Some(def_len.checked_add(7).ok_or(Error::InvalidData)?)
+                }
+            }
+        }
+        0xe0..=0xff => Some(1),
+    };
+    Ok(len)
+}
+
+// Parses a complete token; buf holds exactly token_len(buf) bytes.
+fn parse_token(buf: &[u8]) -> Result<Option<Token>> {
+    let cmd = buf[0];
+    let token = match cmd {
+        0x00..=0x3f => Token::Int(cmd as i32),
+        0x40..=0x7f => Token::NodeIndex((cmd - 0x40 + 1) as u16),
+        0x80..=0xbf => Token::String(read_string(&buf[1..])),
+        0xc0..=0xcf => Token::NodeName(read_string(&buf[1..])),
+        0xd0 => Token::String(String::new()),
+        0xd1 => Token::String(read_string(&buf[2..])),
+        0xd2 => Token::NodeIndex(u16::from_be_bytes([buf[1], buf[2]]).checked_add(1).ok_or(Error::InvalidData)?),
+        0xd3 => Token::Int(i16::from_be_bytes([buf[1], buf[2]]) as i32),
+        0xd4 => Token::Int(i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]])),
+        0xd5 => Token::Float(f32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]])),
+        0xd6 => Token::RawFloat(f32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]])),
+        0xd7 => Token::NodeId(i32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]])),
+        0xd8 => Token::Click,
+        0xd9 => Token::Step(buf[1] as i8),
+        0xda => Token::Root,
+        0xdb => Token::Up,
+        0xdc => Token::DataRequest,
+        0xdd => Token::DefinitionRequest,
+        0xde => Token::End,
+        0xdf => {
+            let start = if buf[1] == 0 && buf[2] == 0 { 7 } else { 3 };
+            Token::NodeDef(buf[start..].to_vec())
+        }
+        0xe0..=0xff => return Ok(None),
+    };
+    Ok(Some(token))
+}
+
+// Strings are UTF-8 on the wire. Anything that isn't valid UTF-8 is replaced with U+FFFD rather
+// than failing, so a single odd name can't stop the rest of the stream from being read.
+fn read_string(buf: &[u8]) -> String {
+    String::from_utf8_lossy(buf).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Feeds the encoded bytes one at a time, on channel 1 like the mixer uses.
+    fn decode_all(encoded: &[u8]) -> Vec<WingResponse> {
+        let mut codec = WingCodec::new();
+        codec.feed(&[0xdf, 0xd1]);
+        let mut responses = Vec::new();
+        for b in encoded {
+            codec.feed(&[*b]);
+            while let Some(response) = codec.decode().unwrap() {
+                responses.push(response);
+            }
+        }
+        responses
+    }
+
+    fn decode_one(encoded: &[u8]) -> (i32, WingNodeData) {
+        let mut responses = decode_all(encoded);
+        assert_eq!(responses.len(), 1);
+        match responses.pop().unwrap() {
+            WingResponse::NodeData(1, id, data) => (id, data),
+            _ => panic!("expected NodeData on channel 1"),
+        }
+    }
+
+    // A value for every position of a 32 bit word holding 0xdf, plus one with all four.
+    fn words_with_df() -> Vec<[u8; 4]> {
+        let mut words = Vec::new();
+        for i in 0..4 {
+            let mut w = [0x12, 0x34, 0x56, 0x78];
+            w[i] = 0xdf;
+            words.push(w);
+        }
+        words.push([0xdf; 4]);
+        words
+    }
+
+    #[test]
+    fn id_round_trip() {
+        for w in words_with_df() {
+            let id = i32::from_be_bytes(w);
+            let mut buf = Vec::new();
+            WingCodec::encode_set_int(id, 5, &mut buf).unwrap();
+            let (rid, data) = decode_one(&buf);
+            assert_eq!(rid, id);
+            assert_eq!(data.get_int(), 5);
+        }
+    }
+
+    #[test]
+    fn float_round_trip() {
+        for w in words_with_df() {
+            let value = f32::from_be_bytes(w);
+            let mut buf = Vec::new();
+            WingCodec::encode_set_float(0x0102df04, value, &mut buf).unwrap();
+            let (id, data) = decode_one(&buf);
+            assert_eq!(id, 0x0102df04);
+            assert_eq!(data.get_float().to_bits(), value.to_bits());
+        }
+    }
+
+    #[test]
+    fn int_round_trip() {
+        let mut values: Vec<i32> = words_with_df().into_iter().map(i32::from_be_bytes).collect();
+        values.extend([0x00df, 0xdf00u16 as i16 as i32, 0xdfdfu16 as i16 as i32]);
+        values.extend([0, 0x3f, 0x40, -1, 32767, -32768, 32768, i32::MIN, i32::MAX]);
+        for value in values {
+            let mut buf = Vec::new();
+            WingCodec::encode_set_int(42, value, &mut buf).unwrap();
+            let (id, data) = decode_one(&buf);
+            assert_eq!(id, 42);
+            assert_eq!(data.get_int(), value);
+        }
+    }
+
+    #[test]
+    fn string_round_trip() {
+        // U+07FF is 0xdf 0xbf in UTF-8
+        for value in ["\u{7ff}", "a\u{7ff}b", "\u{7ff}\u{7ff}\u{7ff}", ""] {
+            let mut buf = Vec::new();
+            WingCodec::encode_set_string(0xdf, value, &mut buf).unwrap();
+            let (id, data) = decode_one(&buf);
+            assert_eq!(id, 0xdf);
+            assert_eq!(data.get_string(), value);
+        }
+    }
+
+    #[test]
+    fn string_lengths() {
+        for len in [1, 63, 64, 65, 255, 256] {
+            let value = "x".repeat(len);
+            let mut buf = Vec::new();
+            WingCodec::encode_set_string(7, &value, &mut buf).unwrap();
+            let (_, data) = decode_one(&buf);
+            assert_eq!(data.get_string(), value);
+        }
+
+        let mut buf = Vec::new();
+        let result = WingCodec::encode_set_string(7, &"x".repeat(257), &mut buf);
+        assert!(matches!(result, Err(Error::StringTooLong(257, MAX_STRING_LEN))));
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_id(7, &mut buf).unwrap();
+        buf.extend([0x82, b'a', 0xff, b'b']);
+        let (_, data) = decode_one(&buf);
+        assert_eq!(data.get_string(), "a\u{fffd}b");
+    }
+
+    #[test]
+    fn navigation_round_trip() {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_id(0x00df0000, &mut buf).unwrap();
+        WingCodec::encode_step(0xdfu8 as i8, &mut buf).unwrap();
+        WingCodec::encode_click(&mut buf).unwrap();
+        for index in [1, 64, 65, 0xe0, 0xdf01, 0xdfe0] {
+            WingCodec::encode_select_index(index, &mut buf).unwrap();
+        }
+        WingCodec::encode_go_up(&mut buf).unwrap();
+        WingCodec::encode_goto_root(&mut buf).unwrap();
+        WingCodec::encode_request_current_node_data(&mut buf).unwrap();
+
+        let responses = decode_all(&buf);
+        assert_eq!(responses.len(), 11);
+        assert!(matches!(responses[0], WingResponse::Step(0x00df0000, -33)));
+        assert!(matches!(responses[1], WingResponse::Click(0x00df0000)));
+        for (response, index) in responses[2..8].iter().zip([1, 64, 65, 0xe0, 0xdf01, 0xdfe0]) {
+            assert!(matches!(response, WingResponse::NodeIndex(i) if *i == index));
+        }
+        assert!(matches!(responses[8], WingResponse::GoUp));
+        assert!(matches!(responses[9], WingResponse::GotoRoot));
+        assert!(matches!(responses[10], WingResponse::DataRequest(0)));
+
+        assert!(matches!(WingCodec::encode_select_index(0, &mut buf), Err(Error::InvalidNodeIndex)));
+    }
+
+    #[test]
+    fn extended_node_def() {
+        for len in [0, 1, 0xffff, 0x10000, 0x12345] {
+            let raw: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+            let mut buf = Vec::new();
+            WingCodec::encode_token(&Token::NodeDef(raw.clone()), &mut buf).unwrap();
+            WingCodec::encode_token(&Token::End, &mut buf).unwrap();
+
+            let mut codec = WingCodec::new();
+            codec.feed(&[0xdf, 0xd1]);
+            for chunk in buf.chunks(1000) {
+                codec.feed(chunk);
+            }
+            assert_eq!(codec.next_token().unwrap(), Some((1, Token::NodeDef(raw))));
+            assert_eq!(codec.next_token().unwrap(), Some((1, Token::End)));
+        }
+    }
+
+    #[test]
+    fn oversized_node_def() {
+        let mut codec = WingCodec::new();
+        codec.feed(&[0xdf, 0xd1, 0xdf, 0xde, 0, 0, 0xff, 0xff, 0xff, 0xff]);
+        assert!(matches!(codec.next_token(), Err(Error::InvalidData)));
+        // what follows is decoded as usual
+        codec.feed(&[0xde]);
+        assert_eq!(codec.next_token().unwrap(), Some((1, Token::End)));
+
+        let mut buf = Vec::new();
+        let raw = vec![0; MAX_NODE_DEF_LEN + 1];
+        assert!(matches!(WingCodec::encode_token(&Token::NodeDef(raw), &mut buf), Err(Error::NodeDefEncode(_))));
+    }
+
+    #[test]
+    fn channels() {
+        let mut buf = vec![0xdf, 0xd1];
+        WingCodec::encode_set_int(1, 1, &mut buf).unwrap();
+        WingCodec::encode_on_channel(Channel::AudioEngine, &[Token::NodeId(2), Token::Int(2)], &mut buf).unwrap();
+        WingCodec::encode_set_int(3, 3, &mut buf).unwrap();
+        WingCodec::encode_on_channel(Channel::Other(13), &[Token::End], &mut buf).unwrap();
+
+        let mut codec = WingCodec::new();
+        codec.feed(&buf);
+        assert!(matches!(codec.decode_channel(Channel::AudioEngine).unwrap(), Some(WingResponse::NodeData(2, 2, _))));
+        assert!(codec.decode_channel(Channel::AudioEngine).unwrap().is_none());
+        assert!(matches!(codec.decode().unwrap(), Some(WingResponse::NodeData(1, 1, _))));
+        assert_eq!(codec.next_token().unwrap(), Some((1, Token::NodeId(3))));
+        assert_eq!(codec.next_token().unwrap(), Some((1, Token::Int(3))));
+        assert_eq!(codec.next_token_on(Channel::Other(13)).unwrap(), Some(Token::End));
+        assert!(codec.decode().unwrap().is_none());
+
+        assert!(WingCodec::encode_on_channel(Channel::Other(14), &[], &mut buf).is_err());
+        assert!(codec.decode_channel(Channel::Other(14)).is_err());
+    }
+
+    #[test]
+    fn channel_ids() {
+        assert_eq!(Channel::Other(1), Channel::ControlEngine);
+        assert_eq!(Channel::from(3), Channel::Meters);
+        assert!(matches!(Channel::from(2), Channel::AudioEngine));
+        assert!(matches!(Channel::from(7), Channel::Other(7)));
+        assert_eq!(Channel::from_id(13), Some(Channel::Other(13)));
+        assert_eq!(Channel::from_id(14), None);
+    }
+
+    #[test]
+    fn before_channel_selected() {
+        let mut buf = Vec::new();
+        WingCodec::encode_set_int(1, 1, &mut buf).unwrap();
+        buf.extend([0xdf, 0xd1]);
+        WingCodec::encode_set_int(2, 2, &mut buf).unwrap();
+
+        let mut codec = WingCodec::new();
+        codec.feed(&buf);
+        assert!(matches!(codec.decode_channel(Channel::ControlEngine).unwrap(), Some(WingResponse::NodeData(-1, 1, _))));
+        assert!(matches!(codec.decode_channel(Channel::ControlEngine).unwrap(), Some(WingResponse::NodeData(1, 2, _))));
+        assert!(codec.decode().unwrap().is_none());
+    }
+
+    #[test]
+    fn unread_channels_are_capped() {
+        let mut buf = vec![0xdf, 0xd4];
+        for i in 0..MAX_QUEUED_TOKENS + 10 {
+            WingCodec::encode_token(&Token::Int(i as i32), &mut buf).unwrap();
+        }
+        buf.extend([0xdf, 0xd1]);
+        for i in 0..MAX_QUEUED_TOKENS + 10 {
+            WingCodec::encode_token(&Token::Int(i as i32), &mut buf).unwrap();
+        }
+
+        let mut codec = WingCodec::new();
+        codec.feed(&buf);
+        // the oldest of channel 4 were dropped, while the control engine's are all kept
+        assert_eq!(codec.next_token_on(Channel::Other(4)).unwrap(), Some(Token::Int(10)));
+        for i in 0..MAX_QUEUED_TOKENS + 10 {
+            assert_eq!(codec.next_token_on(Channel::ControlEngine).unwrap(), Some(Token::Int(i as i32)));
+        }
+    }
+
+    #[test]
+    fn token_round_trip() {
+        let tokens = [
+            Token::Int(0xdf),
+            Token::NodeName("\u{7ff}".to_string()),
+            Token::RawFloat(f32::from_be_bytes([0xdf, 0, 0xdf, 0])),
+            Token::End,
+            Token::NodeDef(vec![0xdf, 1, 0xdf, 0xdf, 2]),
+        ];
+        let mut buf = Vec::new();
+        for token in &tokens {
+            WingCodec::encode_token(token, &mut buf).unwrap();
+        }
+
+        let mut codec = WingCodec::new();
+        codec.feed(&[0xdf, 0xd1]);
+        codec.feed(&buf);
+        for token in tokens {
+            assert_eq!(codec.next_token().unwrap(), Some((1, token)));
+        }
+        assert_eq!(codec.next_token().unwrap(), None);
+    }
+
+    #[test]
+    fn node_name_lengths() {
+        let mut buf = Vec::new();
+        WingCodec::encode_token(&Token::NodeName("x".repeat(16)), &mut buf).unwrap();
+        assert!(matches!(WingCodec::encode_token(&Token::NodeName("x".repeat(17)), &mut buf),
+                         Err(Error::StringTooLong(17, 16))));
+        assert!(matches!(WingCodec::encode_token(&Token::NodeName(String::new()), &mut buf),
+                         Err(Error::EmptyNodeName)));
+    }
+}
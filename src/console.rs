@@ -1,10 +1,17 @@
-use std::collections::HashMap;
-use std::net::{TcpStream, UdpSocket};
-use std::io::{Read, Write};
-use std::time::Duration;
+use std::collections::{HashMap, VecDeque};
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
 
 use crate::{Result, Error, WingResponse};
+use crate::codec::{WingCodec, Token, Channel};
+use crate::builder::WingConsoleBuilder;
+use crate::discovery::{self, DiscoveryInfo};
+use crate::transport::{Transport, TcpTransport};
+use crate::meters::{MeterSource, MeterStream, MeterSubscription};
 use crate::node::{WingNodeDef, WingNodeData};
+use crate::request::{PendingRequest, Outstanding};
+use crate::health::{Health, HealthMonitor};
 use crate::propmap::NAME_TO_DEF;
 
 lazy_static::lazy_static! {
@@ -21,418 +28,853 @@ lazy_static::lazy_static! {
     };
 }
 
-const RX_BUFFER_SIZE: usize = 2048;
+pub(crate) const RX_BUFFER_SIZE: usize = 2048;
+pub(crate) const WING_PORT: u16 = 2222;
+pub(crate) const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(7);
+pub(crate) const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(5);
+// How often to retry sending queued writes while waiting for data.
+const TX_RETRY_INTERVAL: Duration = Duration::from_millis(10);
+// How long queued writes may go without any of them being sent before the connection is given
+// up on, and how many bytes may be queued.
+const TX_TIMEOUT: Duration = Duration::from_secs(5);
+const MAX_TX_QUEUE: usize = 1 << 20;
+
+/// A connection to a mixer, over TCP unless it was made with `WingConsole::from_transport()`.
+pub struct WingConsole<T: Transport = TcpTransport> {
+    reader: WingReader<T>,
+    writer: WingWriter<T>,
+}
 
-pub struct DiscoveryInfo {
-    pub ip:       String,
-    pub name:     String,
-    pub model:    String,
-    pub serial:   String,
-    pub firmware: String,
+/// The receiving half of a `WingConsole`, from `WingConsole::split()`.
+pub struct WingReader<T: Transport = TcpTransport> {
+    // a handle of its own once split, so reading doesn't hold up writers; until then reads go
+    // through the sender's
+    transport:    Option<T>,
+    codec:        WingCodec,
+    rx_buf:       [u8; RX_BUFFER_SIZE],
+    read_timeout: Option<Duration>,
+    // responses read while waiting for a request, still to be returned by read()
+    backlog:      VecDeque<WingResponse>,
+    writer:       WingWriter<T>,
 }
 
-pub struct WingConsole {
-    stream:             TcpStream,
-    current_node_id:    i32,
-    keep_alive_timer:   std::time::Instant,
-    rx_buf:             [u8; RX_BUFFER_SIZE],
-    rx_buf_tail:        usize,
-    rx_buf_size:        usize,
-    rx_esc:             bool,
-    rx_current_channel: i8,
-    rx_has_in_pipe:     Option<u8>,
+/// The sending half of a `WingConsole`, from `WingConsole::split()`. It can be cloned and
+/// shared between threads; all clones send on the same connection.
+pub struct WingWriter<T: Transport = TcpTransport> {
+    sender: Arc<Mutex<Sender<T>>>,
+}
+
+impl<T: Transport> Clone for WingWriter<T> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone() }
+    }
+}
+
+// Everything that writes to the transport, shared by the reader and all writers.
+struct Sender<T: Transport> {
+    transport:           T,
+    tx_queue:            VecDeque<u8>,
+    // when the queue last stopped draining, if it hasn't since
+    tx_stuck_since:      Option<Instant>,
+    tx_timeout:          Duration,
+    keep_alive_interval: Duration,
+    keep_alive_timer:    Instant,
+    meters:              Vec<MeterSubscription>,
+    requests:            VecDeque<Outstanding>,
+    health:              HealthMonitor,
 }
 
 impl WingConsole {
+    /// Looks for mixers by broadcasting on every local network interface's subnet.
     pub fn scan(stop_on_first: bool) -> Result<Vec<DiscoveryInfo>> {
-        let socket = UdpSocket::bind("0.0.0.0:0")?;
-        socket.set_broadcast(true)?;
-        socket.set_read_timeout(Some(Duration::from_millis(500))).unwrap();
-
-        let mut results = Vec::new();
-        let mut attempts = 0;
-
-        socket.send_to(b"WING?", "255.255.255.255:2222")?;
-        while attempts < 10 {
-            let mut buf = [0u8; 1024];
-            match socket.recv_from(&mut buf) {
-                Ok((received, _)) => {
-                    if let Ok(response) = String::from_utf8(buf[..received].to_vec()) {
-                        let tokens: Vec<&str> = response.split(',').collect();
-                        if tokens.len() >= 6 && tokens[0] == "WING" {
-                            results.push(DiscoveryInfo {
-                                ip:       tokens[1].to_string(),
-                                name:     tokens[2].to_string(),
-                                model:    tokens[3].to_string(),
-                                serial:   tokens[4].to_string(),
-                                firmware: tokens[5].to_string(),
-                            });
-                            if stop_on_first {
-                                break;
-                            }
-                        }
-                    }
-                }
-                Err(_) => {
-                    attempts += 1;
-                }
-            }
-        }
+        discovery::scan(stop_on_first, DISCOVERY_TIMEOUT)
+    }
+
+    /// Looks for mixers by sending the discovery probe straight to each of `hosts`, which
+    /// works across routers. Waits up to 5 seconds after the last probe for answers.
+    pub fn scan_hosts(hosts: &[IpAddr]) -> Result<Vec<DiscoveryInfo>> {
+        discovery::scan_hosts(hosts, WING_PORT, DISCOVERY_TIMEOUT)
+    }
 
-        Ok(results)
+    /// Looks for mixers by probing every host in an IPv4 range like `10.0.5.0/24`, as
+    /// `scan_hosts()` does. Ranges wider than a /16 are refused with `Error::InvalidCidr`.
+    pub fn scan_cidr(cidr: &str) -> Result<Vec<DiscoveryInfo>> {
+        discovery::scan_hosts(&discovery::cidr_hosts(cidr)?, WING_PORT, DISCOVERY_TIMEOUT)
     }
 
     pub fn connect(host_or_ip: Option<&str>) -> Result<Self> {
-        let ip =
-            if let Some(i) = host_or_ip {
-                i.to_string()
-            } else {
-                let devices = WingConsole::scan(true)?;
-                if !devices.is_empty() {
-                    devices[0].ip.clone()
-                } else {
-                    return Err(Error::DiscoveryError);
-                }
-            };
+        let mut builder = WingConsole::builder();
+        if let Some(host) = host_or_ip {
+            builder = builder.host(host);
+        }
+        builder.connect()
+    }
+
+    /// Returns a builder for connecting with options other than the defaults.
+    pub fn builder() -> WingConsoleBuilder {
+        WingConsoleBuilder::new()
+    }
+
+    pub fn name_to_id(fullname: &str) -> Option<i32> {
+        if let Ok(num) = fullname.parse::<i32>() {
+            Some(num)
+        } else {
+            NAME_TO_DEF.get(fullname).map(|x| x.id)
+        }
+    }
+    pub fn name_to_def(fullname: &str) -> Option<&WingNodeDef> {
+        NAME_TO_DEF.get(fullname)
+    }
+
+    pub fn id_to_defs(id: i32) -> Option<Vec<(String, WingNodeDef)>> {
+        ID_TO_NAME.get(&id)
+            .cloned()
+            .map(|names|
+                names
+                .iter()
+                .map(|n| (n, NAME_TO_DEF.get(n)))
+                .filter(|x| x.1.is_some())
+                .map(|x| (x.0, x.1.unwrap()))
+                .map(|(n, v)| (n.clone(), v.clone())
+                ).collect())
+    }
+}
+
+impl<T: Transport> WingConsole<T> {
+    /// Runs the protocol over `transport`, which must already be connected to a mixer.
+    pub fn from_transport(transport: T) -> Result<Self> {
+        Self::with_options(transport, KEEP_ALIVE_INTERVAL, None, None)
+    }
+
+    // Sets up a console on a newly connected `transport`, and sends the handshake.
+    pub(crate) fn with_options(transport: T, keep_alive_interval: Duration, read_timeout: Option<Duration>,
+                               health_check: Option<(Duration, Duration)>) -> Result<Self> {
+        let writer = WingWriter {
+            sender: Arc::new(Mutex::new(Sender {
+                transport,
+                tx_queue:            VecDeque::new(),
+                tx_stuck_since:      None,
+                tx_timeout:          TX_TIMEOUT,
+                keep_alive_interval,
+                keep_alive_timer:    Instant::now(),
+                meters:              Vec::new(),
+                requests:            VecDeque::new(),
+                health:              HealthMonitor::new(health_check),
+            })),
+        };
+        let mut buf = Vec::new();
+        WingCodec::encode_keep_alive(&mut buf);
+        writer.write(&buf)?;
 
-        let mut stream = TcpStream::connect((ip, 2222))?;
-        stream.set_nonblocking(true)?;
-        stream.set_nodelay(true)?;
-        stream.write_all(&[0xdf, 0xd1])?;
-        
         Ok(Self {
-            stream,
-            rx_buf: [0; RX_BUFFER_SIZE],
-            rx_buf_tail: 0,
-            rx_buf_size: 0,
-            rx_esc: false,
-            rx_current_channel: -1,
-            rx_has_in_pipe: None,
-            current_node_id: 0,
-            keep_alive_timer: std::time::Instant::now(),
+            reader: WingReader {
+                transport: None,
+                codec: WingCodec::new(),
+                rx_buf: [0; RX_BUFFER_SIZE],
+                read_timeout,
+                backlog: VecDeque::new(),
+                writer: writer.clone(),
+            },
+            writer,
         })
     }
 
+    /// Splits the console into a reader and a writer, so one thread can block in `read()` while
+    /// others send. The writer can be cloned, and the connection stays open until the reader
+    /// and every writer are dropped.
+    ///
+    /// Keep alives and meter renewals are sent from `WingReader::read()`, so keep reading.
+    ///
+    /// This is the only thing that needs `Transport::try_clone()`, and returns its error.
+    pub fn split(mut self) -> Result<(WingReader<T>, WingWriter<T>)> {
+        self.reader.transport = Some(self.writer.sender().transport.try_clone()?);
+        Ok((self.reader, self.writer))
+    }
+
+    /// Reads the next response on `Channel::ControlEngine`, or received before the mixer selected
+    /// a channel. Anything received on other channels is queued for `read_channel()`.
     pub fn read(&mut self) -> Result<WingResponse> {
-        loop {
-            let mut raw = Vec::new(); 
-            let (ch, cmd) = self.decode_next(&mut raw)?;
-            //println!("Channel: {}, Command: {:X}", ch, cmd);
-            if cmd <= 0x3f {
-                let v = cmd as i32;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_i32(v)));
-            } else if cmd <= 0x7f {
-                let v = cmd - 0x40 + 1;
-                println!("REQUEST: NODE INDEX: {}", v);
-            } else if cmd <= 0xbf {
-                let len = cmd - 0x80 + 1;
-                let v = self.read_string(ch, len as usize, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_string(v)));
-            } else if cmd <= 0xcf {
-                let len = cmd - 0xc0 + 1;
-                let v = self.read_string(ch, len as usize, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_string(v)));
-            } else if cmd == 0xd0 {
-                let v = String::new();
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_string(v)));
-            } else if cmd == 0xd1 {
-                let len = self.read_u8(ch, &mut raw)? + 1;
-                let v = self.read_string(ch, len as usize, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_string(v)));
-            } else if cmd == 0xd2 {
-                let v = self.read_u16(ch, &mut raw)? + 1;
-                println!("REQUEST: NODE INDEX: {}", v);
-            } else if cmd == 0xd3 {
-                let v = self.read_i16(ch, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_i16(v)));
-            } else if cmd == 0xd4 {
-                let v = self.read_i32(ch, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_i32(v)));
-            } else if cmd == 0xd5 || cmd == 0xd6 {
-                let v = self.read_f(ch, &mut raw)?;
-                return Ok(WingResponse::NodeData(self.rx_current_channel, self.current_node_id, WingNodeData::with_float(v)));
-            } else if cmd == 0xd7 {
-                self.current_node_id = self.read_i32(ch, &mut raw)?;
-            } else if cmd == 0xd8 {
-                println!("REQUEST: CLICK");
-            } else if cmd == 0xd9 {
-                let v = self.read_i8(ch, &mut raw)?;
-                println!("REQUEST: STEP: {}", v);
-            } else if cmd == 0xda {
-                println!("REQUEST: TREE: GOTO ROOT");
-            } else if cmd == 0xdb {
-                println!("REQUEST: TREE: GO UP 1");
-            } else if cmd == 0xdc {
-                println!("REQUEST: DATA");
-            } else if cmd == 0xdd {
-                println!("REQUEST: CURRENT NODE DEFINITION");
-            } else if cmd == 0xde {
-                return Ok(WingResponse::RequestEnd);
-            } else if cmd == 0xdf {
-                let def_len = self.read_u16(ch, &mut raw)? as u32;
-                if def_len == 0 { let _ = self.read_u32(ch, &mut raw)?; }
-                raw.clear();
-                for _ in 0..def_len { self.decode_next(&mut raw)?; } 
-                return Ok(WingResponse::NodeDef(WingNodeDef::from_bytes(&raw)));
-            }
+        self.reader.read()
+    }
+
+    /// Like `read()`, but gives up and returns `Ok(None)` if nothing arrives within `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<WingResponse>> {
+        self.reader.read_timeout(timeout)
+    }
+
+    /// Returns the next response if one has already been received, or `Ok(None)` without
+    /// waiting.
+    pub fn try_read(&mut self) -> Result<Option<WingResponse>> {
+        self.reader.try_read()
+    }
+
+    /// Reads the next response on `channel`. Anything received on other channels is queued
+    /// until it is read; channels that aren't read keep only their latest 4096 tokens.
+    pub fn read_channel(&mut self, channel: Channel) -> Result<WingResponse> {
+        self.reader.read_channel(channel)
+    }
+
+    /// Sends `tokens` on `channel`.
+    pub fn send(&mut self, channel: Channel, tokens: &[Token]) -> Result<()> {
+        self.writer.send(channel, tokens)
+    }
+
+    /// Returns the latest round trip time and when the mixer was last heard from.
+    pub fn health(&self) -> Health {
+        self.writer.health()
+    }
+
+    /// Sends a ping now, to measure the round trip time, unless one is still waiting for its
+    /// answer. The answer is read by `read()`, and is not returned.
+    pub fn ping(&mut self) -> Result<()> {
+        self.writer.ping()
+    }
+
+    /// Subscribes to the meters of `sources`, returning the stream their data will arrive on.
+    /// The subscription is renewed while the console is being read, so keep calling `read()`.
+    pub fn subscribe_meters(&mut self, sources: &[MeterSource]) -> Result<MeterStream> {
+        self.writer.subscribe_meters(sources)
+    }
+
+    pub(crate) fn add_meter_subscription(&mut self, subscription: MeterSubscription) -> Result<()> {
+        self.writer.add_meter_subscription(subscription)
+    }
+
+    /// Requests the definition of `id`, or of its children if it is a node. Unlike
+    /// `request_node_definition()`, the definitions are returned only through the
+    /// `PendingRequest`, so many requests can be in flight at once.
+    pub fn fetch_node_definitions(&mut self, id: i32) -> Result<PendingRequest<Vec<WingNodeDef>>> {
+        self.writer.fetch_node_definitions(id)
+    }
+
+    /// Requests the data of `id`. Unlike `request_node_data()`, the data is returned only
+    /// through the `PendingRequest`, so many requests can be in flight at once.
+    pub fn fetch_node_data(&mut self, id: i32) -> Result<PendingRequest<Option<WingNodeData>>> {
+        self.writer.fetch_node_data(id)
+    }
+
+    /// Reads until `request` is complete and returns its reply. Anything else read meanwhile
+    /// is kept for `read()`.
+    pub fn wait<R: Default>(&mut self, request: PendingRequest<R>) -> Result<R> {
+        self.reader.wait(request)
+    }
+
+    /// Like `wait()`, but returns `Error::Timeout` if the reply doesn't arrive within `timeout`.
+    pub fn wait_timeout<R: Default>(&mut self, request: PendingRequest<R>, timeout: Duration) -> Result<R> {
+        self.reader.wait_timeout(request, timeout)
+    }
+
+    /// Requests the data of `id` and waits for it. Returns `Error::NotFound` if the mixer has
+    /// no such property, or `Error::Timeout` if it doesn't answer within `timeout`.
+    pub fn get(&mut self, id: i32, timeout: Duration) -> Result<WingNodeData> {
+        let request = self.fetch_node_data(id)?;
+        self.wait_timeout(request, timeout)?.ok_or_else(|| Error::NotFound(id.to_string()))
+    }
+
+    /// Like `get()`, for a property given by its full name, such as "/main/1/mute".
+    pub fn get_by_name(&mut self, name: &str, timeout: Duration) -> Result<WingNodeData> {
+        let id = WingConsole::name_to_id(name).ok_or_else(|| Error::NotFound(name.to_string()))?;
+        self.get(id, timeout)
+    }
+
+    /// Requests the definitions of the children of node `id` and waits for them. Returns
+    /// `Error::NotFound` if the mixer has no such node, or `Error::Timeout` if it doesn't
+    /// answer within `timeout`.
+    pub fn get_children_defs(&mut self, id: i32, timeout: Duration) -> Result<Vec<WingNodeDef>> {
+        let request = self.fetch_node_definitions(id)?;
+        let defs = self.wait_timeout(request, timeout)?;
+        if defs.is_empty() {
+            return Err(Error::NotFound(id.to_string()));
         }
+        Ok(defs.into_iter().filter(|def| def.parent_id == id).collect())
+    }
+
+    pub fn request_node_definition(&mut self, id: i32) -> Result<()> {
+        self.writer.request_node_definition(id)
+    }
+
+    pub fn request_node_data(&mut self, id: i32) -> Result<()> {
+        self.writer.request_node_data(id)
+    }
+
+    pub fn goto_root(&mut self) -> Result<()> {
+        self.writer.goto_root()
     }
 
-    fn read_i8(&mut self, _ch:i8, raw: &mut Vec::<u8>) -> Result<i8> {
-        Ok(self.decode_next(raw)?.1 as i8)
+    pub fn go_up(&mut self) -> Result<()> {
+        self.writer.go_up()
     }
-    fn read_u8(&mut self, _ch:i8, raw: &mut Vec::<u8>) -> Result<u8> {
-        Ok(self.decode_next(raw)?.1)
+
+    pub fn select_index(&mut self, index: u16) -> Result<()> {
+        self.writer.select_index(index)
     }
-    fn read_u16(&mut self, _ch:i8, raw: &mut Vec::<u8>) -> Result<u16> {
-        let a = self.decode_next(raw)?;
-        let b = self.decode_next(raw)?;
-        Ok(((a.1 as u16) << 8) | b.1 as u16)
+
+    pub fn select_id(&mut self, id: i32) -> Result<()> {
+        self.writer.select_id(id)
     }
-    fn read_i16(&mut self, ch:i8, raw: &mut Vec::<u8>) -> Result<i16> {
-        Ok(self.read_u16(ch, raw)? as i16)
+
+    pub fn click(&mut self) -> Result<()> {
+        self.writer.click()
     }
-    fn read_u32(&mut self, _ch:i8, raw: &mut Vec::<u8>) -> Result<u32> {
-        let a = self.decode_next(raw)?;
-        let b = self.decode_next(raw)?;
-        let c = self.decode_next(raw)?;
-        let d = self.decode_next(raw)?;
-        Ok(
-            ((a.1 as u32) << 24) |
-            ((b.1 as u32) << 16) |
-            ((c.1 as u32) << 8) |
-            d.1 as u32
-            )
+
+    pub fn step(&mut self, steps: i8) -> Result<()> {
+        self.writer.step(steps)
     }
-    fn read_i32(&mut self, ch:i8, raw: &mut Vec::<u8>) -> Result<i32> {
-        Ok(self.read_u32(ch, raw)? as i32)
+
+    pub fn request_current_node_data(&mut self) -> Result<()> {
+        self.writer.request_current_node_data()
     }
 
-    fn read_string(&mut self, _ch:i8, len:usize, raw: &mut Vec::<u8>) -> Result<String> {
-        // define u8 array of size len and fill it with decode_next
-        let buf = (0..len).map(|_| self.decode_next(raw).map(|(_, v)| v)).collect::<Result<Vec<u8>>>()?;
-        // convert u8 array to string
-        String::from_utf8(buf).map_err(|_| Error::InvalidData)
+    pub fn request_current_node_definition(&mut self) -> Result<()> {
+        self.writer.request_current_node_definition()
     }
 
-    fn read_f(&mut self, _ch:i8, raw: &mut Vec::<u8>) -> Result<f32> {
-        let a = self.decode_next(raw)?;
-        let b = self.decode_next(raw)?;
-        let c = self.decode_next(raw)?;
-        let d = self.decode_next(raw)?;
-        let val = ((a.1 as u32) << 24) |
-            ((b.1 as u32) << 16) |
-            ((c.1 as u32) << 8) |
-            d.1 as u32;
-        Ok(f32::from_bits(val))
+    pub fn set_string(&mut self, id: i32, value: &str) -> Result<()> {
+        self.writer.set_string(id, value)
     }
 
-    fn keep_alive(&mut self) {
-        if self.keep_alive_timer.elapsed() > Duration::from_secs(7) {
-            self.stream.write_all(&[0xdf, 0xd1]).unwrap();
-            self.keep_alive_timer = std::time::Instant::now();
+    pub fn set_float(&mut self, id: i32, value: f32) -> Result<()> {
+        self.writer.set_float(id, value)
+    }
+
+    pub fn set_int(&mut self, id: i32, value: i32) -> Result<()> {
+        self.writer.set_int(id, value)
+    }
+}
+
+// Checks `value` against the longest string any property with this id allows.
+pub(crate) fn check_string_len(id: i32, value: &str) -> Result<()> {
+    let max_len = WingConsole::id_to_defs(id)
+        .and_then(|defs| defs.iter().filter_map(|(_, def)| def.max_string_len).max());
+    if let Some(max_len) = max_len {
+        if value.len() > max_len as usize {
+            return Err(Error::StringTooLong(value.len(), max_len as usize));
+        }
+    }
+    Ok(())
+}
+
+impl<T: Transport> WingReader<T> {
+    /// Reads the next response on `Channel::ControlEngine`, or received before the mixer selected
+    /// a channel. Anything received on other channels is queued for `read_channel()`.
+    pub fn read(&mut self) -> Result<WingResponse> {
+        self.read_channel(Channel::ControlEngine)
+    }
+
+    /// Like `read()`, but gives up and returns `Ok(None)` if nothing arrives within `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<WingResponse>> {
+        self.read_until(Channel::ControlEngine, Instant::now().checked_add(timeout))
+    }
+
+    /// Returns the next response if one has already been received, or `Ok(None)` without
+    /// waiting.
+    pub fn try_read(&mut self) -> Result<Option<WingResponse>> {
+        self.read_until(Channel::ControlEngine, Some(Instant::now()))
+    }
+
+    /// Reads the next response on `channel`. Anything received on other channels is queued
+    /// until it is read; channels that aren't read keep only their latest 4096 tokens.
+    ///
+    /// If the console was built with a read timeout, this returns `Error::Timeout` when nothing
+    /// arrives in time.
+    pub fn read_channel(&mut self, channel: Channel) -> Result<WingResponse> {
+        let deadline = self.read_timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        match self.read_until(channel, deadline)? {
+            Some(response) => Ok(response),
+            None => Err(Error::Timeout),
         }
     }
 
-    fn decode_next(&mut self, raw: &mut Vec::<u8>) -> Result<(i8, u8)> {
-        if self.rx_has_in_pipe.is_some() {
-            // println!("has in pipe");
-            let value = self.rx_has_in_pipe.unwrap();
-            self.rx_has_in_pipe = None;
-            raw.push(value);
-            return Ok((self.rx_current_channel, value));
+    /// Reads until `request` is complete and returns its reply. Anything else read meanwhile
+    /// is kept for `read()`.
+    ///
+    /// If the reader was built with a read timeout, this returns `Error::Timeout` when nothing
+    /// arrives in time.
+    pub fn wait<R: Default>(&mut self, request: PendingRequest<R>) -> Result<R> {
+        let deadline = self.read_timeout.and_then(|timeout| Instant::now().checked_add(timeout));
+        self.wait_until(request, deadline)
+    }
+
+    /// Like `wait()`, but returns `Error::Timeout` if the reply doesn't arrive within `timeout`.
+    pub fn wait_timeout<R: Default>(&mut self, request: PendingRequest<R>, timeout: Duration) -> Result<R> {
+        self.wait_until(request, Instant::now().checked_add(timeout))
+    }
+
+    fn wait_until<R: Default>(&mut self, request: PendingRequest<R>, deadline: Option<Instant>) -> Result<R> {
+        while !request.is_done() {
+            if let Some(response) = self.codec.decode_channel(Channel::ControlEngine)? {
+                if let Some(response) = self.route(response) {
+                    self.backlog.push_back(response);
+                }
+            } else if !self.fill_rx(deadline)? {
+                return Err(Error::Timeout);
+            }
         }
+        request.wait_timeout(Duration::ZERO)
+    }
 
+    fn read_until(&mut self, channel: Channel, deadline: Option<Instant>) -> Result<Option<WingResponse>> {
+        let control = channel == Channel::ControlEngine;
+        if control {
+            if let Some(response) = self.backlog.pop_front() {
+                return Ok(Some(response));
+            }
+        }
         loop {
-            self.keep_alive();
-            if self.rx_buf_size == 0 {
-                loop {
-                    match self.stream.read(&mut self.rx_buf) {
-                        Ok(n) if n > 0 => {
-                            // println!("got n {}...", n);
-                            self.rx_buf_size = n;
-                            self.rx_buf_tail = 0;
-                            break;
-                        }
-                        // check for blocking error
-                        Err(ref e) if e.kind() == std::io::ErrorKind::WouldBlock => {
-                            self.keep_alive();
-                            std::thread::sleep(Duration::from_millis(10));
-                            continue;
-                        }
-                        Ok(_) => return Err(Error::ConnectionError),
-                        Err(e) => return Err(e.into()),
-                    }
+            if let Some(response) = self.codec.decode_channel(channel)? {
+                if !control {
+                    return Ok(Some(response));
+                }
+                if let Some(response) = self.route(response) {
+                    return Ok(Some(response));
                 }
+                continue;
             }
+            if !self.fill_rx(deadline)? {
+                return Ok(None);
+            }
+        }
+    }
 
-            let byte = self.rx_buf[self.rx_buf_tail];
-            // println!("rx_buf_tail: {}, rx_buf_size: {}, byte: {:X} buf: {}",
-            //     self.rx_buf_tail,
-            //     self.rx_buf_size, byte,
-            //     self.rx_buf.iter().map(|x| x.to_string()).collect::<Vec<String>>().join(","));
-            self.rx_buf_tail += 1;
-            self.rx_buf_size -= 1;
-
-            if ! self.rx_esc {
-                if byte == 0xdf {
-                    self.rx_esc = true;
-                } else {
-                    raw.push(byte);
-                    break Ok((self.rx_current_channel, byte))
+    // Gives `response` to the oldest outstanding request if it belongs to it, otherwise returns
+    // it to be passed on.
+    fn route(&mut self, response: WingResponse) -> Option<WingResponse> {
+        let mut sender = self.writer.sender();
+        if let WingResponse::RequestEnd = response {
+            match sender.requests.pop_front() {
+                Some(Outstanding::Ping) => {
+                    sender.health.ponged();
+                    None
                 }
-            } else if byte == 0xdf {
-                break Ok((self.rx_current_channel, byte))
-            } else {
-                self.rx_esc = false;
-                if byte == 0xde {
-                    raw.push(0xdf);
-                    break Ok((self.rx_current_channel, 0xdf))
-                } else if (0xd0..0xde).contains(&byte) {
-                    self.rx_current_channel = (byte - 0xd0) as i8;
-                    continue;
-                } else if self.rx_current_channel >= 0 {
-                    self.rx_has_in_pipe = Some(byte);
-                    raw.push(0xdf);
-                    break Ok((self.rx_current_channel, 0xdf))
-                } else {
-                    raw.push(byte);
-                    break Ok((self.rx_current_channel, byte))
+                Some(request) => request.end().then_some(response),
+                None => Some(response),
+            }
+        } else {
+            match sender.requests.front_mut() {
+                Some(request) => request.offer(response),
+                None => Some(response),
+            }
+        }
+    }
+
+    // Blocks until something is received, returning false if `deadline` passes first. The wait
+    // is cut short whenever a keep alive, ping, meter renewal or queued write is due, so those
+    // still go out. If health checks are on, returns `Error::Stalled` once when the mixer has
+    // gone quiet; reading again carries on waiting for it.
+    fn fill_rx(&mut self, deadline: Option<Instant>) -> Result<bool> {
+        loop {
+            let mut wait = self.writer.sender().service()?;
+            if let Some(deadline) = deadline {
+                wait = wait.min(deadline.saturating_duration_since(Instant::now()));
+            }
+
+            let read = match self.transport.as_mut() {
+                Some(transport) => transport.read(&mut self.rx_buf, wait),
+                None => self.writer.sender().transport.read(&mut self.rx_buf, wait),
+            };
+            match read {
+                Ok(n) if n > 0 => {
+                    self.writer.sender().health.received();
+                    self.codec.feed(&self.rx_buf[..n]);
+                    return Ok(true);
                 }
+                Ok(_) => return Err(Error::ConnectionError),
+                // timed out
+                Err(ref e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    self.writer.sender().health.check_stalled()?;
+                    if deadline.is_some_and(|d| Instant::now() >= d) {
+                        return Ok(false);
+                    }
+                }
+                Err(e) => return Err(e.into()),
             }
         }
     }
+}
+
+impl<T: Transport> WingWriter<T> {
+    fn sender(&self) -> MutexGuard<'_, Sender<T>> {
+        crate::lock(&self.sender)
+    }
 
-    fn format_id(&self, id: i32, buf: &mut Vec<u8>, prefix: u8, suffix: Option<u8>) {
-        buf.push(prefix);
+    fn write(&self, buf: &[u8]) -> Result<()> {
+        self.sender().write(buf)
+    }
 
-        let b1 = ((id >> 24) & 0xFF) as u8;
-        let b2 = ((id >> 16) & 0xFF) as u8;
-        let b3 = ((id >>  8) & 0xFF) as u8;
-        let b4 = ((id      ) & 0xFF) as u8;
+    /// Returns the latest round trip time and when the mixer was last heard from.
+    pub fn health(&self) -> Health {
+        self.sender().health.health()
+    }
 
-        buf.push(b1); if b1 == 0xdf { buf.push(0xde); }
-        buf.push(b2); if b2 == 0xdf { buf.push(0xde); }
-        buf.push(b3); if b3 == 0xdf { buf.push(0xde); }
-        buf.push(b4); if b4 == 0xdf { buf.push(0xde); }
+    /// Sends a ping now, to measure the round trip time, unless one is still waiting for its
+    /// answer. The answer is read by `WingReader::read()`, and is not returned.
+    pub fn ping(&self) -> Result<()> {
+        self.sender().ping()
+    }
 
-        if let Some(suffix1) = suffix {
-            buf.push(suffix1);
+    // Writes a request, noting that a RequestEnd will come back for it once it has been queued.
+    fn write_request(&self, buf: &[u8], request: Outstanding) -> Result<()> {
+        let mut sender = self.sender();
+        sender.queue(buf)?;
+        sender.requests.push_back(request);
+        sender.flush()
+    }
+
+    /// Sends `tokens` on `channel`.
+    pub fn send(&self, channel: Channel, tokens: &[Token]) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_on_channel(channel, tokens, &mut buf)?;
+        let mut sender = self.sender();
+        sender.queue(&buf)?;
+        if channel == Channel::ControlEngine {
+            for token in tokens {
+                if matches!(token, Token::DataRequest | Token::DefinitionRequest) {
+                    sender.requests.push_back(Outstanding::Untracked);
+                }
+            }
         }
+        sender.flush()
     }
 
-    pub fn request_node_definition(&mut self, id: i32) -> Result<()> {
+    /// Requests the definition of `id`, or of its children if it is a node. Unlike
+    /// `request_node_definition()`, the definitions are returned only through the
+    /// `PendingRequest`, so many requests can be in flight at once.
+    pub fn fetch_node_definitions(&self, id: i32) -> Result<PendingRequest<Vec<WingNodeDef>>> {
         let mut buf = Vec::new();
-        if id == 0 {
-            buf.push(0xda);
-            buf.push(0xdd);
-        } else {
-            self.format_id(id, &mut buf, 0xd7, Some(0xdd));
-        };
-        self.stream.write_all(&buf)?;
-        Ok(())
+        WingCodec::encode_request_node_definition(id, &mut buf)?;
+        let (outstanding, request) = Outstanding::definition(id);
+        self.write_request(&buf, outstanding)?;
+        Ok(request)
     }
 
-    pub fn request_node_data(&mut self, id: i32) -> Result<()> {
+    /// Requests the data of `id`. Unlike `request_node_data()`, the data is returned only
+    /// through the `PendingRequest`, so many requests can be in flight at once.
+    pub fn fetch_node_data(&self, id: i32) -> Result<PendingRequest<Option<WingNodeData>>> {
         let mut buf = Vec::new();
-        if id == 0 {
-            buf.push(0xda);
-            buf.push(0xdc);
-        } else {
-            self.format_id(id, &mut buf, 0xd7, Some(0xdc));
-        };
-        self.stream.write_all(&buf)?;
+        WingCodec::encode_request_node_data(id, &mut buf)?;
+        let (outstanding, request) = Outstanding::data(id);
+        self.write_request(&buf, outstanding)?;
+        Ok(request)
+    }
+
+    /// Subscribes to the meters of `sources`, returning the stream their data will arrive on.
+    /// The subscription is renewed by `WingReader::read()`, so keep reading.
+    pub fn subscribe_meters(&self, sources: &[MeterSource]) -> Result<MeterStream> {
+        let (stream, subscription) = MeterStream::new(sources)?;
+        self.add_meter_subscription(subscription)?;
+        Ok(stream)
+    }
+
+    // Sends the request of `subscription` and keeps it renewed.
+    pub(crate) fn add_meter_subscription(&self, subscription: MeterSubscription) -> Result<()> {
+        let mut sender = self.sender();
+        sender.write(subscription.request())?;
+        sender.meters.push(subscription);
         Ok(())
     }
 
-    pub fn set_string(&mut self, id: i32, value: &str) -> Result<()> {
+    pub fn request_node_definition(&self, id: i32) -> Result<()> {
         let mut buf = Vec::new();
-        self.format_id(id, &mut buf, 0xd7, None);
-
-        if value.is_empty() {
-            buf.push(0xd0);
-        } else if value.len() <= 64 {
-            buf.push(0x7f + value.len() as u8);
-        } else if value.len() <= 256 {
-            buf.push(0xd1);
-            buf.push((value.len()-1) as u8);
-        }
+        WingCodec::encode_request_node_definition(id, &mut buf)?;
+        self.write_request(&buf, Outstanding::Untracked)
+    }
 
-        for c in value.bytes() {
-            buf.push(c);
-            // do we need this escaping? i guess 0xdf never really shows up in strings unless its
-            // unicode stuff that the wing probably doesn't support
-            // if c == 0xdf { buf.push(0xde); }
-        }
-        self.stream.write_all(&buf)?;
-        Ok(())
+    pub fn request_node_data(&self, id: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_node_data(id, &mut buf)?;
+        self.write_request(&buf, Outstanding::Untracked)
     }
 
-    pub fn set_float(&mut self, id: i32, value: f32) -> Result<()> {
+    pub fn goto_root(&self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_goto_root(&mut buf)?;
+        self.write(&buf)
+    }
+
+    pub fn go_up(&self) -> Result<()> {
         let mut buf = Vec::new();
-        self.format_id(id, &mut buf, 0xd7, Some(0xd5));
+        WingCodec::encode_go_up(&mut buf)?;
+        self.write(&buf)
+    }
 
-        let bytes = value.to_be_bytes();
-        buf.push(bytes[0]);
-        buf.push(bytes[1]);
-        buf.push(bytes[2]);
-        buf.push(bytes[3]);
+    pub fn select_index(&self, index: u16) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_index(index, &mut buf)?;
+        self.write(&buf)
+    }
 
-        self.stream.write_all(&buf)?;
-        Ok(())
+    pub fn select_id(&self, id: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_select_id(id, &mut buf)?;
+        self.write(&buf)
     }
 
-    pub fn set_int(&mut self, id: i32, value: i32) -> Result<()> {
+    pub fn click(&self) -> Result<()> {
         let mut buf = Vec::new();
-        self.format_id(id, &mut buf, 0xd7, None);
+        WingCodec::encode_click(&mut buf)?;
+        self.write(&buf)
+    }
 
-        let bytes = value.to_be_bytes();
+    pub fn step(&self, steps: i8) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_step(steps, &mut buf)?;
+        self.write(&buf)
+    }
 
-        if (0..=0x3f).contains(&value) {
-            buf.push(value as u8);
-        } else if (-32768..=32767).contains(&value) {
-            buf.push(0xd3);
-            buf.push(bytes[0]);
-            buf.push(bytes[1]);
-        } else {
-            buf.push(0xd4);
-            buf.push(bytes[0]);
-            buf.push(bytes[1]);
-            buf.push(bytes[2]);
-            buf.push(bytes[3]);
-        }
+    pub fn request_current_node_data(&self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_current_node_data(&mut buf)?;
+        self.write_request(&buf, Outstanding::Untracked)
+    }
+
+    pub fn request_current_node_definition(&self) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_request_current_node_definition(&mut buf)?;
+        self.write_request(&buf, Outstanding::Untracked)
+    }
+
+    pub fn set_string(&self, id: i32, value: &str) -> Result<()> {
+        check_string_len(id, value)?;
+        let mut buf = Vec::new();
+        WingCodec::encode_set_string(id, value, &mut buf)?;
+        self.write(&buf)
+    }
+
+    pub fn set_float(&self, id: i32, value: f32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_set_float(id, value, &mut buf)?;
+        self.write(&buf)
+    }
 
-        self.stream.write_all(&buf)?;
+    pub fn set_int(&self, id: i32, value: i32) -> Result<()> {
+        let mut buf = Vec::new();
+        WingCodec::encode_set_int(id, value, &mut buf)?;
+        self.write(&buf)
+    }
+}
+
+impl<T: Transport> Sender<T> {
+    // Queues `buf` behind anything not yet sent, and sends as much as the socket will take.
+    fn write(&mut self, buf: &[u8]) -> Result<()> {
+        self.queue(buf)?;
+        self.flush()
+    }
+
+    // Queues `buf` behind anything not yet sent. What is already queued is sent first, so a
+    // writer that never reads still drains it. `buf` is queued only if this succeeds; it fails
+    // with `Error::QueueFull` if there is no room for it.
+    fn queue(&mut self, buf: &[u8]) -> Result<()> {
+        self.flush()?;
+        if self.tx_queue.len() + buf.len() > MAX_TX_QUEUE {
+            return Err(Error::QueueFull);
+        }
+        self.tx_queue.extend(buf);
         Ok(())
     }
 
-    pub fn name_to_id(fullname: &str) -> Option<i32> {
-        if let Ok(num) = fullname.parse::<i32>() {
-            Some(num)
-        } else {
-            NAME_TO_DEF.get(fullname).map(|x| x.id)
+    // Sends queued bytes until the queue is empty or the socket stops taking them. Whatever
+    // isn't sent stays queued, in order, for the next write or read. Fails with
+    // `Error::Timeout` once nothing has been taken for `tx_timeout`.
+    fn flush(&mut self) -> Result<()> {
+        while !self.tx_queue.is_empty() {
+            let (pending, _) = self.tx_queue.as_slices();
+            match self.transport.write(pending) {
+                Ok(0) => return Err(Error::ConnectionError),
+                Ok(n) => {
+                    self.tx_queue.drain(..n);
+                    self.tx_stuck_since = None;
+                }
+                Err(ref e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(ref e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                    let since = *self.tx_stuck_since.get_or_insert_with(Instant::now);
+                    if since.elapsed() >= self.tx_timeout {
+                        return Err(Error::Timeout);
+                    }
+                    return Ok(());
+                }
+                Err(e) => return Err(e.into()),
+            }
         }
+        Ok(())
     }
-    pub fn name_to_def(fullname: &str) -> Option<&WingNodeDef> {
-        NAME_TO_DEF.get(fullname)
+
+    // Writes a ping, which is a data request for the current node, so it doesn't change which
+    // node is current. Only one is sent at a time. Like keep alives, pings are queued even when
+    // the queue is full, so one can't be refused once it is tracked.
+    fn ping(&mut self) -> Result<()> {
+        if self.health.health().ping_pending.is_some() {
+            return Ok(());
+        }
+        let mut buf = Vec::new();
+        WingCodec::encode_request_current_node_data(&mut buf)?;
+        self.tx_queue.extend(&buf);
+        self.health.pinged();
+        self.requests.push_back(Outstanding::Ping);
+        self.flush()
     }
 
-    pub fn id_to_defs(id: i32) -> Option<Vec<(String, WingNodeDef)>> {
-        ID_TO_NAME.get(&id)
-            .cloned()
-            .map(|names|
-                names
-                .iter()
-                .map(|n| (n, NAME_TO_DEF.get(n)))
-                .filter(|x| x.1.is_some())
-                .map(|x| (x.0, x.1.unwrap()))
-                .map(|(n, v)| (n.clone(), v.clone())
-                ).collect())
+    // Sends whatever keep alive, ping, meter renewals and queued writes are due, and returns how
+    // long until something else will be.
+    fn service(&mut self) -> Result<Duration> {
+        if self.health.ping_due() {
+            self.ping()?;
+        }
+        if self.keep_alive_timer.elapsed() >= self.keep_alive_interval {
+            let mut buf = Vec::new();
+            WingCodec::encode_keep_alive(&mut buf);
+            self.tx_queue.extend(&buf);
+            self.keep_alive_timer = Instant::now();
+        }
+        self.meters.retain(|m| m.is_alive());
+        for subscription in self.meters.iter_mut() {
+            if let Some(request) = subscription.renewal() {
+                self.tx_queue.extend(request);
+            }
+        }
+        self.flush()?;
+
+        let mut wait = self.keep_alive_interval.saturating_sub(self.keep_alive_timer.elapsed());
+        for subscription in self.meters.iter() {
+            wait = wait.min(subscription.until_renewal());
+        }
+        if !self.tx_queue.is_empty() {
+            wait = wait.min(TX_RETRY_INTERVAL);
+        }
+        Ok(wait.min(self.health.until_ping()).min(self.health.until_stalled()))
     }
 }
 
-impl Drop for WingConsole {
+impl<T: Transport> Drop for Sender<T> {
     fn drop(&mut self) {
-        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+        let _ = self.flush();
+        let _ = self.transport.close();
+        for request in self.requests.drain(..) {
+            request.abandon();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pipe_console, pipe_console_with};
+
+    #[test]
+    fn partial_writes() {
+        let (mut wing, pipe) = pipe_console();
+        pipe.pipe().write_limit = Some(3);
+
+        let mut expected = vec![0xdf, 0xd1];
+        for id in 1..=20 {
+            wing.set_float(id, 0.5).unwrap();
+            WingCodec::encode_set_float(id, 0.5, &mut expected).unwrap();
+        }
+        assert_eq!(pipe.take_sent(), expected);
+    }
+
+    #[test]
+    fn stuck_writes() {
+        let (mut wing, pipe) = pipe_console();
+        pipe.take_sent();
+        pipe.pipe().write_limit = Some(0);
+        wing.writer.sender().tx_timeout = Duration::from_millis(50);
+
+        // queued while the mixer isn't taking anything
+        wing.set_int(1, 1).unwrap();
+        assert!(pipe.take_sent().is_empty());
+        std::thread::sleep(Duration::from_millis(60));
+        // a write that times out isn't queued
+        assert!(matches!(wing.set_int(2, 2), Err(Error::Timeout)));
+        assert!(matches!(wing.try_read(), Err(Error::Timeout)));
+
+        // once it takes data again, what was queued goes out in order
+        pipe.pipe().write_limit = None;
+        wing.set_int(3, 3).unwrap();
+        let mut expected = Vec::new();
+        for id in [1, 3] {
+            WingCodec::encode_set_int(id, id, &mut expected).unwrap();
+        }
+        assert_eq!(pipe.take_sent(), expected);
+    }
+
+    #[test]
+    fn full_queue() {
+        let (wing, pipe) = pipe_console();
+        let (_reader, writer) = wing.split().unwrap();
+        pipe.pipe().write_limit = Some(0);
+
+        let value = "x".repeat(200);
+        let mut queued = 0;
+        let error = loop {
+            match writer.set_string(1234567, &value) {
+                Ok(()) => queued += 1,
+                Err(e) => break e,
+            }
+        };
+        assert!(matches!(error, Error::QueueFull));
+        let mut message = Vec::new();
+        WingCodec::encode_set_string(1234567, &value, &mut message).unwrap();
+        assert_eq!(queued, (MAX_TX_QUEUE - 2) / message.len());
+
+        // the next write sends the queue first, without anything being read
+        pipe.pipe().write_limit = None;
+        writer.set_string(1234567, &value).unwrap();
+        assert_eq!(pipe.take_sent().len(), 2 + (queued + 1) * message.len());
+    }
+
+    #[test]
+    fn huge_timeouts() {
+        let (mut wing, pipe) = pipe_console_with(Some(Duration::MAX), None);
+
+        // too long to have a deadline, so they wait as if there were no timeout
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03, 0xd7, 0, 0, 0, 6, 0x04]);
+        assert!(matches!(wing.read_timeout(Duration::MAX).unwrap(), Some(WingResponse::NodeData(1, 5, _))));
+        assert!(matches!(wing.read().unwrap(), WingResponse::NodeData(1, 6, _)));
+
+        let request = wing.fetch_node_data(7).unwrap();
+        pipe.receive(&[0xd7, 0, 0, 0, 7, 0x05, 0xde]);
+        assert_eq!(wing.wait_timeout(request, Duration::MAX).unwrap().unwrap().get_int(), 5);
+        let request = wing.fetch_node_data(8).unwrap();
+        pipe.receive(&[0xd7, 0, 0, 0, 8, 0x06, 0xde]);
+        assert_eq!(wing.wait(request).unwrap().unwrap().get_int(), 6);
+    }
+
+    #[test]
+    fn split() {
+        let (wing, pipe) = pipe_console();
+        let (mut reader, writer) = wing.split().unwrap();
+
+        let reading = std::thread::spawn(move || reader.read().map(|response| (response, reader)));
+
+        let writers: Vec<_> = (0..4).map(|t| {
+            let writer = writer.clone();
+            std::thread::spawn(move || {
+                for i in 0..50 {
+                    writer.set_int(t * 1000 + i, i).unwrap();
+                }
+            })
+        }).collect();
+        for w in writers {
+            w.join().unwrap();
+        }
+        assert!(!reading.is_finished());
+
+        // every message arrived whole
+        let mut codec = WingCodec::new();
+        codec.feed(&pipe.take_sent());
+        let mut sets = Vec::new();
+        while let Some(response) = codec.decode().unwrap() {
+            match response {
+                WingResponse::NodeData(1, id, data) => sets.push((id, data.get_int())),
+                _ => panic!("unexpected response"),
+            }
+        }
+        sets.sort();
+        let expected: Vec<_> = (0..4).flat_map(|t| (0..50).map(move |i| (t * 1000 + i, i))).collect();
+        assert_eq!(sets, expected);
+
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03]);
+        let (response, _reader) = reading.join().unwrap().unwrap();
+        assert!(matches!(response, WingResponse::NodeData(1, 5, _)));
     }
 }
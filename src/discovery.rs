@@ -0,0 +1,749 @@
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs, UdpSocket};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error};
+use crate::console::WING_PORT;
+
+const PROBE: &[u8] = b"WING?";
+// How long to wait between unicast probes, so sweeping a subnet doesn't flood it.
+const PROBE_INTERVAL: Duration = Duration::from_millis(2);
+// The largest range `scan_cidr` sweeps: a /16, which takes a couple of minutes at the rate above.
+const MIN_PREFIX: u8 = 16;
+// How long each of the monitor's probes listens for replies.
+const MONITOR_LISTEN: Duration = Duration::from_secs(1);
+// How many probes in a row a mixer has to miss to count as gone, since replies can get lost.
+const MONITOR_MISSES: u32 = 3;
+
+/// A mixer that answered discovery.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveryInfo {
+    pub ip:               String,
+    pub name:             String,
+    pub model:            WingModel,
+    pub serial:           String,
+    /// The firmware as the mixer reports it, such as `3.0.5-0-g0c2b9d4a:release`.
+    pub firmware:         String,
+    /// `firmware` parsed, if it could be.
+    pub firmware_version: Option<FirmwareVersion>,
+    /// Where the reply came from.
+    pub addr:             SocketAddr,
+    /// The reply itself, `WING,ip,name,model,serial,firmware`.
+    pub reply:            String,
+    /// The local interface the reply arrived on, if the probe went out a particular one.
+    pub interface:        Option<NetInterface>,
+}
+
+/// The kind of mixer, from the model in its discovery reply.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum WingModel {
+    /// `wing`
+    Wing,
+    /// `wing-bk`, the full console in black.
+    WingBk,
+    /// `ngc-full`, the full console in white.
+    NgcFull,
+    /// `wing-compact`
+    WingCompact,
+    /// `wing-rack`
+    WingRack,
+    /// Any other model, as reported.
+    Unknown(String),
+}
+
+impl WingModel {
+    /// The model as it appears in discovery replies.
+    pub fn as_str(&self) -> &str {
+        match self {
+            WingModel::Wing => "wing",
+            WingModel::WingBk => "wing-bk",
+            WingModel::NgcFull => "ngc-full",
+            WingModel::WingCompact => "wing-compact",
+            WingModel::WingRack => "wing-rack",
+            WingModel::Unknown(model) => model,
+        }
+    }
+}
+
+impl From<&str> for WingModel {
+    fn from(model: &str) -> Self {
+        match model {
+            "wing" => WingModel::Wing,
+            "wing-bk" => WingModel::WingBk,
+            "ngc-full" => WingModel::NgcFull,
+            "wing-compact" => WingModel::WingCompact,
+            "wing-rack" => WingModel::WingRack,
+            _ => WingModel::Unknown(model.to_string()),
+        }
+    }
+}
+
+impl fmt::Display for WingModel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A firmware version like `3.0.5-0-g0c2b9d4a:release`: major, minor and patch, then the
+/// build, the commit and the release channel, which can be missing.
+///
+/// Versions compare by their numbers only, in that order, so `FirmwareVersion::new(3, 1, 0)`
+/// can be used to check for a feature: any 3.1.0 build compares `>=` to it, whatever its
+/// commit and channel.
+#[derive(Clone, Debug)]
+pub struct FirmwareVersion {
+    pub major:   u32,
+    pub minor:   u32,
+    pub patch:   u32,
+    pub build:   u32,
+    /// The commit hash, without the leading `g`. Empty if missing.
+    pub commit:  String,
+    /// Such as `release`. Empty if missing.
+    pub channel: String,
+}
+
+impl FirmwareVersion {
+    pub fn new(major: u32, minor: u32, patch: u32) -> Self {
+        Self { major, minor, patch, build: 0, commit: String::new(), channel: String::new() }
+    }
+
+    fn key(&self) -> (u32, u32, u32, u32) {
+        (self.major, self.minor, self.patch, self.build)
+    }
+}
+
+impl PartialEq for FirmwareVersion {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for FirmwareVersion {}
+
+impl PartialOrd for FirmwareVersion {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for FirmwareVersion {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+impl Hash for FirmwareVersion {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key().hash(state);
+    }
+}
+
+impl FromStr for FirmwareVersion {
+    type Err = Error;
+
+    fn from_str(version: &str) -> Result<Self> {
+        let invalid = || Error::InvalidFirmwareVersion(version.to_string());
+        let (rest, channel) = version.trim().split_once(':').unwrap_or((version.trim(), ""));
+        let (numbers, rest) = rest.split_once('-').unwrap_or((rest, ""));
+        let (build, commit) = rest.split_once('-').unwrap_or((rest, ""));
+
+        let numbers: Vec<u32> = numbers.split('.')
+            .map(|n| n.parse().map_err(|_| invalid()))
+            .collect::<Result<_>>()?;
+        let [major, minor, patch] = numbers[..] else {
+            return Err(invalid());
+        };
+        Ok(Self {
+            major,
+            minor,
+            patch,
+            build:   if build.is_empty() { 0 } else { build.parse().map_err(|_| invalid())? },
+            commit:  commit.strip_prefix('g').unwrap_or(commit).to_string(),
+            channel: channel.to_string(),
+        })
+    }
+}
+
+impl fmt::Display for FirmwareVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)?;
+        if !self.commit.is_empty() {
+            write!(f, "-{}-g{}", self.build, self.commit)?;
+        } else if self.build != 0 {
+            write!(f, "-{}", self.build)?;
+        }
+        if !self.channel.is_empty() {
+            write!(f, ":{}", self.channel)?;
+        }
+        Ok(())
+    }
+}
+
+/// A local IPv4 network interface that discovery probes are broadcast from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NetInterface {
+    pub name:      String,
+    pub addr:      Ipv4Addr,
+    pub netmask:   Ipv4Addr,
+    pub broadcast: Ipv4Addr,
+}
+
+impl NetInterface {
+    /// Lists the interfaces that are up and can broadcast, leaving out loopback. Only
+    /// implemented on unix; elsewhere the list is empty.
+    pub fn all() -> Result<Vec<NetInterface>> {
+        Ok(list_interfaces()?)
+    }
+
+    fn new(name: String, addr: Ipv4Addr, netmask: Ipv4Addr) -> Self {
+        // broadcast = ip | ~subnet, as Discovery.md explains
+        let broadcast = Ipv4Addr::from(u32::from(addr) | !u32::from(netmask));
+        Self { name, addr, netmask, broadcast }
+    }
+}
+
+#[cfg(unix)]
+fn list_interfaces() -> std::io::Result<Vec<NetInterface>> {
+    let mut ifap: *mut libc::ifaddrs = std::ptr::null_mut();
+    if unsafe { libc::getifaddrs(&mut ifap) } != 0 {
+        return Err(std::io::Error::last_os_error());
+    }
+
+    let wanted = (libc::IFF_UP | libc::IFF_BROADCAST) as libc::c_uint;
+    let mut interfaces = Vec::new();
+    let mut cur = ifap;
+    while !cur.is_null() {
+        // SAFETY: getifaddrs returned a valid list, which is freed only after this loop
+        let ifa = unsafe { &*cur };
+        cur = ifa.ifa_next;
+        if ifa.ifa_addr.is_null() || ifa.ifa_netmask.is_null()
+            || ifa.ifa_flags & wanted != wanted
+            || ifa.ifa_flags & libc::IFF_LOOPBACK as libc::c_uint != 0 {
+            continue;
+        }
+        // SAFETY: the addresses are non-null, and AF_INET ones are sockaddr_in
+        unsafe {
+            if (*ifa.ifa_addr).sa_family as libc::c_int != libc::AF_INET {
+                continue;
+            }
+            let addr = &*(ifa.ifa_addr as *const libc::sockaddr_in);
+            let mask = &*(ifa.ifa_netmask as *const libc::sockaddr_in);
+            let name = std::ffi::CStr::from_ptr(ifa.ifa_name).to_string_lossy().into_owned();
+            interfaces.push(NetInterface::new(
+                name,
+                Ipv4Addr::from(u32::from_be(addr.sin_addr.s_addr)),
+                Ipv4Addr::from(u32::from_be(mask.sin_addr.s_addr)),
+            ));
+        }
+    }
+    unsafe { libc::freeifaddrs(ifap) };
+    Ok(interfaces)
+}
+
+#[cfg(not(unix))]
+fn list_interfaces() -> std::io::Result<Vec<NetInterface>> {
+    Ok(Vec::new())
+}
+
+// Parses a `WING,ip,name,model,serial,firmware` reply.
+pub(crate) fn parse_reply(reply: &[u8], addr: SocketAddr, interface: Option<&NetInterface>) -> Option<DiscoveryInfo> {
+    let reply = std::str::from_utf8(reply).ok()?;
+    let tokens: Vec<&str> = reply.split(',').collect();
+    if tokens.len() < 6 || tokens[0] != "WING" {
+        return None;
+    }
+    Some(DiscoveryInfo {
+        ip:               tokens[1].to_string(),
+        name:             tokens[2].to_string(),
+        model:            WingModel::from(tokens[3]),
+        serial:           tokens[4].to_string(),
+        firmware:         tokens[5].to_string(),
+        firmware_version: tokens[5].parse().ok(),
+        addr,
+        reply:            reply.to_string(),
+        interface:        interface.cloned(),
+    })
+}
+
+// How long until `deadline`, or forever if there is none.
+fn until(deadline: Option<Instant>) -> Duration {
+    deadline.map_or(Duration::MAX, |d| d.saturating_duration_since(Instant::now()))
+}
+
+// Broadcasts the probe to the subnet of every local interface, or to 255.255.255.255 if there
+// are none, and collects replies until `timeout` has passed, or the first one if
+// `stop_on_first`. A mixer heard on more than one interface is only listed once.
+pub(crate) fn scan(stop_on_first: bool, timeout: Duration) -> Result<Vec<DiscoveryInfo>> {
+    let interfaces = NetInterface::all().unwrap_or_default();
+    // a timeout too long to have a deadline doesn't have one
+    let deadline = Instant::now().checked_add(timeout);
+    let results = Mutex::new(Vec::<DiscoveryInfo>::new());
+    let done = AtomicBool::new(false);
+
+    let probe = |interface: Option<&NetInterface>| -> Result<()> {
+        let (local, target) = match interface {
+            Some(i) => (i.addr, i.broadcast),
+            None => (Ipv4Addr::UNSPECIFIED, Ipv4Addr::BROADCAST),
+        };
+        let socket = UdpSocket::bind((local, 0))?;
+        socket.set_broadcast(true)?;
+        socket.send_to(PROBE, (target, WING_PORT))?;
+
+        let mut buf = [0u8; 1024];
+        while !done.load(Ordering::Relaxed) {
+            let remaining = until(deadline);
+            if remaining.is_zero() {
+                break;
+            }
+            // wake up now and then to notice another interface finding the first mixer
+            socket.set_read_timeout(Some(remaining.min(Duration::from_millis(100))))?;
+            let Ok((received, from)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            if let Some(info) = parse_reply(&buf[..received], from, interface) {
+                let mut results = crate::lock(&results);
+                if !results.iter().any(|r| r.serial == info.serial) {
+                    results.push(info);
+                }
+                if stop_on_first {
+                    done.store(true, Ordering::Relaxed);
+                }
+            }
+        }
+        Ok(())
+    };
+
+    if interfaces.is_empty() {
+        probe(None)?;
+    } else {
+        let outcomes: Vec<Result<()>> = std::thread::scope(|scope| {
+            let threads: Vec<_> = interfaces.iter()
+                .map(|interface| scope.spawn(|| probe(Some(interface))))
+                .collect();
+            threads.into_iter()
+                .map(|t| t.join().unwrap_or(Ok(())))
+                .collect()
+        });
+        // one interface failing, say because it went down, doesn't spoil the others
+        if outcomes.iter().all(|o| o.is_err()) {
+            if let Some(Err(e)) = outcomes.into_iter().next() {
+                return Err(e);
+            }
+        }
+    }
+
+    Ok(results.into_inner().unwrap_or_else(|e| e.into_inner()))
+}
+
+// Sends the probe to `port` on each of `hosts` in turn, `PROBE_INTERVAL` apart, and collects
+// replies until every host has answered or `timeout` has passed since the last probe went out.
+pub(crate) fn scan_hosts(hosts: &[IpAddr], port: u16, timeout: Duration) -> Result<Vec<DiscoveryInfo>> {
+    let deadline = u32::try_from(hosts.len()).ok()
+        .and_then(|n| PROBE_INTERVAL.checked_mul(n))
+        .and_then(|probing| probing.checked_add(timeout))
+        .and_then(|wait| Instant::now().checked_add(wait));
+    let results = Mutex::new(Vec::<DiscoveryInfo>::new());
+
+    let probe = |local: IpAddr, hosts: Vec<IpAddr>| -> Result<()> {
+        let socket = UdpSocket::bind((local, 0))?;
+        let mut unanswered: HashSet<IpAddr> = hosts.iter().copied().collect();
+        let mut to_send = hosts.into_iter();
+        let mut next_send = Some(Instant::now());
+
+        let mut buf = [0u8; 1024];
+        while !unanswered.is_empty() {
+            let now = Instant::now();
+            if until(deadline).is_zero() {
+                break;
+            }
+            if next_send.is_some_and(|at| now >= at) {
+                next_send = match to_send.next() {
+                    Some(host) => {
+                        // an unreachable host shouldn't stop the rest from being probed
+                        let _ = socket.send_to(PROBE, (host, port));
+                        Some(now + PROBE_INTERVAL)
+                    }
+                    None => None,
+                };
+                continue;
+            }
+
+            let wait = until(next_send).min(until(deadline));
+            socket.set_read_timeout(Some(wait.max(Duration::from_millis(1))))?;
+            let Ok((received, from)) = socket.recv_from(&mut buf) else {
+                continue;
+            };
+            if let Some(info) = parse_reply(&buf[..received], from, None) {
+                unanswered.remove(&from.ip());
+                let mut results = crate::lock(&results);
+                if !results.iter().any(|r| r.serial == info.serial) {
+                    results.push(info);
+                }
+            }
+        }
+        Ok(())
+    };
+
+    // IPv4 and IPv6 hosts need sockets of their own, so each gets a thread
+    let (v4, v6): (Vec<IpAddr>, Vec<IpAddr>) = hosts.iter().partition(|h| h.is_ipv4());
+    let outcomes: Vec<Result<()>> = std::thread::scope(|scope| {
+        let threads: Vec<_> = [(IpAddr::from(Ipv4Addr::UNSPECIFIED), v4), (IpAddr::from(Ipv6Addr::UNSPECIFIED), v6)]
+            .into_iter()
+            .filter(|(_, hosts)| !hosts.is_empty())
+            .map(|(local, hosts)| scope.spawn(move || probe(local, hosts)))
+            .collect();
+        threads.into_iter()
+            .map(|t| t.join().unwrap_or(Ok(())))
+            .collect()
+    });
+    if !outcomes.is_empty() && outcomes.iter().all(|o| o.is_err()) {
+        if let Some(Err(e)) = outcomes.into_iter().next() {
+            return Err(e);
+        }
+    }
+
+    Ok(results.into_inner().unwrap_or_else(|e| e.into_inner()))
+}
+
+// Lists the host addresses in an IPv4 range like `10.0.5.0/24`, leaving out the network and
+// broadcast addresses of ranges that have them.
+pub(crate) fn cidr_hosts(cidr: &str) -> Result<Vec<IpAddr>> {
+    let invalid = || Error::InvalidCidr(cidr.to_string());
+    let (addr, prefix) = cidr.split_once('/').ok_or_else(invalid)?;
+    let addr: Ipv4Addr = addr.trim().parse().map_err(|_| invalid())?;
+    let prefix: u8 = prefix.trim().parse().map_err(|_| invalid())?;
+    if !(MIN_PREFIX..=32).contains(&prefix) {
+        return Err(invalid());
+    }
+
+    let mask = u32::MAX << (32 - prefix as u32);
+    let network = u32::from(addr) & mask;
+    let broadcast = network | !mask;
+    let hosts = if prefix >= 31 { network..=broadcast } else { network + 1..=broadcast - 1 };
+    Ok(hosts.map(|a| IpAddr::from(Ipv4Addr::from(a))).collect())
+}
+
+/// A change in the mixers on the network, from `DiscoveryMonitor`. Mixers are told apart by
+/// serial number.
+// events are few and far between, so boxing Changed to save space isn't worth it
+#[allow(clippy::large_enum_variant)]
+#[derive(Clone, Debug, PartialEq)]
+pub enum DiscoveryEvent {
+    /// A mixer was found.
+    Appeared(DiscoveryInfo),
+    /// A known mixer answered with a different IP, name, model or firmware.
+    Changed { old: DiscoveryInfo, new: DiscoveryInfo },
+    /// A known mixer stopped answering.
+    Disappeared(DiscoveryInfo),
+}
+
+/// Keeps discovering mixers in the background, reporting them as they come and go.
+///
+/// The network is probed every few seconds from a thread of its own, which stops when the
+/// monitor is dropped.
+pub struct DiscoveryMonitor {
+    events: mpsc::Receiver<DiscoveryEvent>,
+    mixers: Arc<Mutex<HashMap<String, DiscoveryInfo>>>,
+    stop:   Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+// A mixer the monitor knows about, and how many probes in a row it has missed.
+struct Known {
+    info:   DiscoveryInfo,
+    misses: u32,
+}
+
+impl DiscoveryMonitor {
+    /// Starts probing every 3 seconds.
+    pub fn start() -> Self {
+        Self::with_interval(Duration::from_secs(3))
+    }
+
+    /// Starts probing every `interval`. Each probe listens for a second, so shorter intervals
+    /// probe back to back.
+    pub fn with_interval(interval: Duration) -> Self {
+        let (event_tx, events) = mpsc::channel();
+        let (stop, stop_rx) = mpsc::channel::<()>();
+        let mixers = Arc::new(Mutex::new(HashMap::new()));
+        let shared = mixers.clone();
+
+        let thread = std::thread::spawn(move || {
+            let mut known = HashMap::new();
+            loop {
+                let started = Instant::now();
+                // a failed probe, say with the network down, counts as hearing nothing
+                let found = scan(false, MONITOR_LISTEN).unwrap_or_default();
+                for event in update(&mut known, found) {
+                    if event_tx.send(event).is_err() {
+                        return;
+                    }
+                }
+                *crate::lock(&shared) =
+                    known.iter().map(|(serial, k)| (serial.clone(), k.info.clone())).collect();
+
+                // returns early, with Disconnected, once the monitor is dropped
+                let wait = interval.saturating_sub(started.elapsed());
+                if !matches!(stop_rx.recv_timeout(wait), Err(mpsc::RecvTimeoutError::Timeout)) {
+                    return;
+                }
+            }
+        });
+
+        Self { events, mixers, stop: Some(stop), thread: Some(thread) }
+    }
+
+    /// Blocks until the next event.
+    pub fn recv(&self) -> Option<DiscoveryEvent> {
+        self.events.recv().ok()
+    }
+
+    /// Waits at most `timeout` for the next event.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<DiscoveryEvent> {
+        self.events.recv_timeout(timeout).ok()
+    }
+
+    /// Returns the next event if there is one, without waiting.
+    pub fn try_recv(&self) -> Option<DiscoveryEvent> {
+        self.events.try_recv().ok()
+    }
+
+    /// The mixers currently on the network, as of the last probe.
+    pub fn mixers(&self) -> Vec<DiscoveryInfo> {
+        crate::lock(&self.mixers).values().cloned().collect()
+    }
+}
+
+impl Drop for DiscoveryMonitor {
+    fn drop(&mut self) {
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+// Compares the mixers `found` by a probe with those already `known`, returning what changed.
+fn update(known: &mut HashMap<String, Known>, found: Vec<DiscoveryInfo>) -> Vec<DiscoveryEvent> {
+    let mut events = Vec::new();
+    for k in known.values_mut() {
+        k.misses += 1;
+    }
+    for info in found {
+        match known.get_mut(&info.serial) {
+            Some(k) => {
+                k.misses = 0;
+                let changed = k.info.ip != info.ip || k.info.name != info.name
+                    || k.info.model != info.model || k.info.firmware != info.firmware;
+                let old = std::mem::replace(&mut k.info, info);
+                if changed {
+                    events.push(DiscoveryEvent::Changed { old, new: k.info.clone() });
+                }
+            }
+            None => {
+                events.push(DiscoveryEvent::Appeared(info.clone()));
+                known.insert(info.serial.clone(), Known { info, misses: 0 });
+            }
+        }
+    }
+    known.retain(|_, k| {
+        if k.misses >= MONITOR_MISSES {
+            events.push(DiscoveryEvent::Disappeared(k.info.clone()));
+            false
+        } else {
+            true
+        }
+    });
+    events
+}
+
+/// The mixer's side of discovery, for testing and demos without a mixer: answers each probe
+/// with a reply like `WING,ip,name,model,serial,firmware`, from a thread of its own, until
+/// dropped.
+pub struct DiscoveryResponder {
+    addr:   SocketAddr,
+    stop:   Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl DiscoveryResponder {
+    /// Answers probes sent to UDP port 2222 on any interface, as a mixer would. None of the
+    /// fields can contain a comma, since that separates them in the reply.
+    pub fn start(ip: &str, name: &str, model: &WingModel, serial: &str, firmware: &str) -> Result<Self> {
+        Self::bind((Ipv4Addr::UNSPECIFIED, WING_PORT), &responder_reply(ip, name, model, serial, firmware)?)
+    }
+
+    /// Answers probes sent to `addr` with `reply`, which is sent as is.
+    pub fn bind(addr: impl ToSocketAddrs, reply: &str) -> Result<Self> {
+        let socket = UdpSocket::bind(addr)?;
+        // wake up now and then to notice being dropped
+        socket.set_read_timeout(Some(Duration::from_millis(100)))?;
+        let addr = socket.local_addr()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let stopped = stop.clone();
+        let reply = reply.as_bytes().to_vec();
+        let thread = std::thread::spawn(move || {
+            let mut buf = [0u8; 64];
+            while !stopped.load(Ordering::Relaxed) {
+                let Ok((received, from)) = socket.recv_from(&mut buf) else {
+                    continue;
+                };
+                if &buf[..received] == PROBE {
+                    let _ = socket.send_to(&reply, from);
+                }
+            }
+        });
+
+        Ok(Self { addr, stop, thread: Some(thread) })
+    }
+
+    /// The address probes are answered on.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.addr
+    }
+}
+
+// Builds the reply a mixer would send, refusing fields that would split it wrongly.
+fn responder_reply(ip: &str, name: &str, model: &WingModel, serial: &str, firmware: &str) -> Result<String> {
+    let model = model.to_string();
+    for field in [ip, name, &model, serial, firmware] {
+        if field.contains(',') {
+            return Err(Error::InvalidReplyField(field.to_string()));
+        }
+    }
+    Ok(format!("WING,{},{},{},{},{}", ip, name, model, serial, firmware))
+}
+
+impl Drop for DiscoveryResponder {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn broadcast_address() {
+        let i = NetInterface::new("en0".into(), Ipv4Addr::new(192, 168, 1, 23), Ipv4Addr::new(255, 255, 255, 0));
+        assert_eq!(i.broadcast, Ipv4Addr::new(192, 168, 1, 255));
+        let i = NetInterface::new("en1".into(), Ipv4Addr::new(10, 0, 5, 9), Ipv4Addr::new(255, 255, 252, 0));
+        assert_eq!(i.broadcast, Ipv4Addr::new(10, 0, 7, 255));
+    }
+
+    #[test]
+    fn reply() {
+        let from = SocketAddr::from(([192, 168, 1, 19], WING_PORT));
+        let reply = "WING,192.168.1.19,WING-PP-93892216,wing-compact,01XXXUX06X3AEX,3.0.5-0-g0c2b9d4a:release";
+        let info = parse_reply(reply.as_bytes(), from, None).unwrap();
+        assert_eq!(info.ip, "192.168.1.19");
+        assert_eq!(info.model, WingModel::WingCompact);
+        assert_eq!(info.firmware, "3.0.5-0-g0c2b9d4a:release");
+        assert_eq!(info.firmware_version.unwrap().to_string(), info.firmware);
+        assert_eq!(info.addr, from);
+        assert_eq!(info.reply, reply);
+
+        let info = parse_reply(b"WING,10.0.0.2,Stage,wing-mini,S2,beta", from, None).unwrap();
+        assert_eq!(info.model, WingModel::Unknown("wing-mini".into()));
+        assert_eq!(info.model.to_string(), "wing-mini");
+        assert!(info.firmware_version.is_none());
+
+        assert!(parse_reply(b"WING,1.2.3.4", from, None).is_none());
+        assert!(parse_reply(b"XR18,1,2,3,4,5", from, None).is_none());
+    }
+
+    #[test]
+    fn firmware_version() {
+        let v: FirmwareVersion = "3.0.5-0-g0c2b9d4a:release".parse().unwrap();
+        assert_eq!((v.major, v.minor, v.patch, v.build), (3, 0, 5, 0));
+        assert_eq!((v.commit.as_str(), v.channel.as_str()), ("0c2b9d4a", "release"));
+
+        assert!(v >= FirmwareVersion::new(3, 0, 5));
+        assert!(v < FirmwareVersion::new(3, 1, 0));
+        assert!(v > "2.10.0".parse().unwrap());
+        assert!("3.0.5-2-gffffffff".parse::<FirmwareVersion>().unwrap() > v);
+        assert_eq!("3.1.0".parse::<FirmwareVersion>().unwrap(), FirmwareVersion::new(3, 1, 0));
+
+        // the commit and channel don't count
+        let v: FirmwareVersion = "3.1.0-0-g00000001:beta".parse().unwrap();
+        assert_eq!(v, FirmwareVersion::new(3, 1, 0));
+        assert_eq!(v.cmp(&"3.1.0-0-gffffffff:release".parse().unwrap()), cmp::Ordering::Equal);
+        assert!(v >= FirmwareVersion::new(3, 1, 0));
+        assert_eq!(HashSet::from([v, FirmwareVersion::new(3, 1, 0)]).len(), 1);
+
+        for bad in ["", "3.0", "3.0.5.1", "3.x.5", "3.0.5-x-g0c2b9d4a"] {
+            assert!(matches!(bad.parse::<FirmwareVersion>(), Err(Error::InvalidFirmwareVersion(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn monitor_events() {
+        let mixer = |ip: &str, firmware: &str| {
+            let reply = format!("WING,{},Wing,wing-rack,S1,{}", ip, firmware);
+            parse_reply(reply.as_bytes(), SocketAddr::from(([10, 0, 0, 2], WING_PORT)), None).unwrap()
+        };
+        let mut known = HashMap::new();
+
+        assert_eq!(update(&mut known, vec![mixer("10.0.0.2", "3.0.5")]), [DiscoveryEvent::Appeared(mixer("10.0.0.2", "3.0.5"))]);
+        assert!(update(&mut known, vec![mixer("10.0.0.2", "3.0.5")]).is_empty());
+        assert_eq!(update(&mut known, vec![mixer("10.0.0.2", "3.1.0")]),
+                   [DiscoveryEvent::Changed { old: mixer("10.0.0.2", "3.0.5"), new: mixer("10.0.0.2", "3.1.0") }]);
+
+        // a lost reply or two doesn't make a mixer disappear
+        for _ in 1..MONITOR_MISSES {
+            assert!(update(&mut known, vec![]).is_empty());
+        }
+        assert_eq!(update(&mut known, vec![]), [DiscoveryEvent::Disappeared(mixer("10.0.0.2", "3.1.0"))]);
+        assert!(known.is_empty());
+    }
+
+    #[test]
+    fn cidr() {
+        let hosts = cidr_hosts("10.0.5.0/24").unwrap();
+        assert_eq!(hosts.len(), 254);
+        assert_eq!(hosts[0], IpAddr::from([10, 0, 5, 1]));
+        assert_eq!(hosts[253], IpAddr::from([10, 0, 5, 254]));
+        assert_eq!(cidr_hosts("10.0.5.77/30").unwrap(), [IpAddr::from([10, 0, 5, 77]), IpAddr::from([10, 0, 5, 78])]);
+        assert_eq!(cidr_hosts("10.0.5.77/32").unwrap(), [IpAddr::from([10, 0, 5, 77])]);
+        assert_eq!(cidr_hosts("10.0.0.0/16").unwrap().len(), 65534);
+
+        for bad in ["10.0.5.0", "10.0.5.0/33", "10.0.0.0/8", "10.0.5/24", "fe80::/64"] {
+            assert!(matches!(cidr_hosts(bad), Err(Error::InvalidCidr(_))), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn responder() {
+        let answer = responder_reply("127.0.0.1", "Test", &WingModel::WingRack, "S3", "3.0.5-0-g0c2b9d4a:release").unwrap();
+        let responder = DiscoveryResponder::bind((Ipv4Addr::LOCALHOST, 0), &answer).unwrap();
+        let port = responder.local_addr().port();
+
+        let found = scan_hosts(&[IpAddr::from(Ipv4Addr::LOCALHOST)], port, Duration::from_secs(2)).unwrap();
+        assert_eq!(found.len(), 1);
+        // a timeout too long for a deadline still ends once every host has answered
+        assert_eq!(scan_hosts(&[IpAddr::from(Ipv4Addr::LOCALHOST)], port, Duration::MAX).unwrap().len(), 1);
+        assert_eq!(found[0].reply, "WING,127.0.0.1,Test,wing-rack,S3,3.0.5-0-g0c2b9d4a:release");
+        assert_eq!(found[0].model, WingModel::WingRack);
+        assert_eq!(found[0].addr, SocketAddr::from((Ipv4Addr::LOCALHOST, port)));
+    }
+
+    #[test]
+    fn responder_fields() {
+        assert!(matches!(DiscoveryResponder::start("127.0.0.1", "Front, left", &WingModel::WingRack, "S3", "3.0.5"),
+                         Err(Error::InvalidReplyField(f)) if f == "Front, left"));
+        let model = WingModel::Unknown("wing,mini".into());
+        assert!(matches!(responder_reply("127.0.0.1", "Test", &model, "S3", "3.0.5"), Err(Error::InvalidReplyField(_))));
+    }
+}
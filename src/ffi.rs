@@ -1,7 +1,7 @@
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_int, c_float};
 use std::ptr;
-use crate::{WingConsole, NodeType, NodeUnit, WingResponse};
+use crate::{WingConsole, WingResponse};
 
 // Opaque type wrappers
 #[repr(C)]
@@ -25,6 +25,13 @@ pub enum ResponseType {
     End = 0,
     NodeDefinition = 1,
     NodeData = 2,
+    NodeIndex = 3,
+    Click = 4,
+    Step = 5,
+    GotoRoot = 6,
+    GoUp = 7,
+    DataRequest = 8,
+    DefinitionRequest = 9,
 }
 
 #[no_mangle]
@@ -62,7 +69,7 @@ pub extern "C" fn wing_discover_count(handle: *const WingDiscoveryInfoHandle) ->
 #[no_mangle]
 pub extern "C" fn wing_discover_get_ip(handle: *const WingDiscoveryInfoHandle, index: c_int) -> *const c_char {
     unsafe {
-        let info = &(*handle).info[index as usize];
+        let info = &(&(*handle).info)[index as usize];
         CString::new(&info.ip[..]).unwrap().into_raw()
     }
 }
@@ -70,7 +77,7 @@ pub extern "C" fn wing_discover_get_ip(handle: *const WingDiscoveryInfoHandle, i
 #[no_mangle]
 pub extern "C" fn wing_discover_get_name(handle: *const WingDiscoveryInfoHandle, index: c_int) -> *const c_char {
     unsafe {
-        let info = &(*handle).info[index as usize];
+        let info = &(&(*handle).info)[index as usize];
         CString::new(&info.name[..]).unwrap().into_raw()
     }
 }
@@ -78,15 +85,15 @@ pub extern "C" fn wing_discover_get_name(handle: *const WingDiscoveryInfoHandle,
 #[no_mangle]
 pub extern "C" fn wing_discover_get_model(handle: *const WingDiscoveryInfoHandle, index: c_int) -> *const c_char {
     unsafe {
-        let info = &(*handle).info[index as usize];
-        CString::new(&info.model[..]).unwrap().into_raw()
+        let info = &(&(*handle).info)[index as usize];
+        CString::new(info.model.as_str()).unwrap().into_raw()
     }
 }
 
 #[no_mangle]
 pub extern "C" fn wing_discover_get_serial(handle: *const WingDiscoveryInfoHandle, index: c_int) -> *const c_char {
     unsafe {
-        let info = &(*handle).info[index as usize];
+        let info = &(&(*handle).info)[index as usize];
         CString::new(&info.serial[..]).unwrap().into_raw()
     }
 }
@@ -94,7 +101,7 @@ pub extern "C" fn wing_discover_get_serial(handle: *const WingDiscoveryInfoHandl
 #[no_mangle]
 pub extern "C" fn wing_discover_get_firmware(handle: *const WingDiscoveryInfoHandle, index: c_int) -> *const c_char {
     unsafe {
-        let info = &(*handle).info[index as usize];
+        let info = &(&(*handle).info)[index as usize];
         CString::new(&info.firmware[..]).unwrap().into_raw()
     }
 }
@@ -207,14 +214,46 @@ pub extern "C" fn wing_response_get_type(handle: *const ResponseHandle) -> Respo
         WingResponse::RequestEnd => ResponseType::End,
         WingResponse::NodeDef(_) => ResponseType::NodeDefinition,
         WingResponse::NodeData(_, _, _) => ResponseType::NodeData,
+        WingResponse::NodeIndex(_) => ResponseType::NodeIndex,
+        WingResponse::Click(_) => ResponseType::Click,
+        WingResponse::Step(_, _) => ResponseType::Step,
+        WingResponse::GotoRoot => ResponseType::GotoRoot,
+        WingResponse::GoUp => ResponseType::GoUp,
+        WingResponse::DataRequest(_) => ResponseType::DataRequest,
+        WingResponse::DefinitionRequest(_) => ResponseType::DefinitionRequest,
     }
 }
 
 #[no_mangle]
 pub extern "C" fn wing_node_data_get_id(handle: *const ResponseHandle) -> i32 {
     unsafe {
-        if let WingResponse::NodeData(_, id, _) = &(*handle).response {
-            *id
+        match &(*handle).response {
+            WingResponse::NodeData(_, id, _) |
+            WingResponse::Click(id) |
+            WingResponse::Step(id, _) |
+            WingResponse::DataRequest(id) |
+            WingResponse::DefinitionRequest(id) => *id,
+            _ => 0,
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wing_response_get_node_index(handle: *const ResponseHandle) -> u16 {
+    unsafe {
+        if let WingResponse::NodeIndex(index) = &(*handle).response {
+            *index
+        } else {
+            0
+        }
+    }
+}
+
+#[no_mangle]
+pub extern "C" fn wing_response_get_step(handle: *const ResponseHandle) -> c_int {
+    unsafe {
+        if let WingResponse::Step(_, step) = &(*handle).response {
+            *step as c_int
         } else {
             0
         }
@@ -338,10 +377,10 @@ pub extern "C" fn wing_node_definition_get_index(def: *const ResponseHandle) ->
 }
 
 #[no_mangle]
-pub extern "C" fn wing_node_definition_get_type(def: *const ResponseHandle) -> NodeType {
+pub extern "C" fn wing_node_definition_get_type(def: *const ResponseHandle) -> c_int {
     unsafe {
         if let WingResponse::NodeDef(def) = &(*def).response {
-            def.node_type
+            u8::from(def.node_type) as c_int
         } else {
             panic!("Invalid response type");
         }
@@ -349,10 +388,10 @@ pub extern "C" fn wing_node_definition_get_type(def: *const ResponseHandle) -> N
 }
 
 #[no_mangle]
-pub extern "C" fn wing_node_definition_get_unit(def: *const ResponseHandle) -> NodeUnit {
+pub extern "C" fn wing_node_definition_get_unit(def: *const ResponseHandle) -> c_int {
     unsafe {
         if let WingResponse::NodeDef(def) = &(*def).response {
-            def.unit
+            u8::from(def.unit) as c_int
         } else {
             panic!("Invalid response type");
         }
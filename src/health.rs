@@ -0,0 +1,170 @@
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error};
+
+/// How the connection to the mixer is doing, from `WingConsole::health()`.
+#[derive(Copy, Clone, Debug)]
+pub struct Health {
+    /// The round trip time of the most recent ping, once one has been answered.
+    pub rtt:           Option<Duration>,
+    /// When anything was last received from the mixer.
+    pub last_received: Instant,
+    /// When the ping still waiting for an answer was sent. No other is sent until it is answered.
+    pub ping_pending:  Option<Instant>,
+}
+
+// Sends pings and watches for a connection that has gone quiet.
+pub(crate) struct HealthMonitor {
+    // how often to ping, and how long without hearing anything counts as stalled
+    check:     Option<(Duration, Duration)>,
+    last_ping: Instant,
+    // the current stall has been reported, so it isn't again until something is received
+    stalled:   bool,
+    health:    Health,
+}
+
+impl HealthMonitor {
+    pub(crate) fn new(check: Option<(Duration, Duration)>) -> Self {
+        Self {
+            check,
+            last_ping: Instant::now(),
+            stalled:   false,
+            health: Health {
+                rtt:           None,
+                last_received: Instant::now(),
+                ping_pending:  None,
+            },
+        }
+    }
+
+    pub(crate) fn health(&self) -> Health {
+        self.health
+    }
+
+    pub(crate) fn received(&mut self) {
+        self.health.last_received = Instant::now();
+        self.stalled = false;
+    }
+
+    pub(crate) fn ping_due(&self) -> bool {
+        self.until_ping().is_zero()
+    }
+
+    pub(crate) fn pinged(&mut self) {
+        self.last_ping = Instant::now();
+        self.health.ping_pending = Some(self.last_ping);
+    }
+
+    pub(crate) fn ponged(&mut self) {
+        self.health.rtt = self.health.ping_pending.take().map(|sent| sent.elapsed());
+    }
+
+    // How long until the next ping is due, or forever if pings are off or one is unanswered.
+    pub(crate) fn until_ping(&self) -> Duration {
+        match self.check {
+            Some((interval, _)) if self.health.ping_pending.is_none() => {
+                interval.saturating_sub(self.last_ping.elapsed())
+            }
+            _ => Duration::MAX,
+        }
+    }
+
+    // How long until the connection counts as stalled, or forever if that has already been
+    // reported or health checks are off.
+    pub(crate) fn until_stalled(&self) -> Duration {
+        match self.check {
+            Some((_, stall_timeout)) if !self.stalled => {
+                stall_timeout.saturating_sub(self.health.last_received.elapsed())
+            }
+            _ => Duration::MAX,
+        }
+    }
+
+    // Returns `Error::Stalled` if nothing has been received for too long, unless that has
+    // already been reported since the last time something was.
+    pub(crate) fn check_stalled(&mut self) -> Result<()> {
+        if self.until_stalled().is_zero() {
+            self.stalled = true;
+            return Err(Error::Stalled(self.health.last_received.elapsed()));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{pipe_console, pipe_console_with};
+    use crate::WingResponse;
+
+    #[test]
+    fn ping() {
+        let (mut wing, pipe) = pipe_console();
+        pipe.take_sent();
+
+        wing.ping().unwrap();
+        assert_eq!(pipe.take_sent(), [0xdc]);
+        assert!(wing.health().ping_pending.is_some());
+
+        // only one at a time
+        wing.ping().unwrap();
+        assert!(pipe.take_sent().is_empty());
+
+        // the answer is the current node's data, which read() doesn't return
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03, 0xde, 0xd7, 0, 0, 0, 6, 0x04]);
+        assert!(matches!(wing.try_read().unwrap(), Some(WingResponse::NodeData(_, 6, _))));
+        assert!(wing.try_read().unwrap().is_none());
+        let health = wing.health();
+        assert!(health.rtt.is_some());
+        assert!(health.ping_pending.is_none());
+
+        wing.ping().unwrap();
+        assert_eq!(pipe.take_sent(), [0xdc]);
+    }
+
+    #[test]
+    fn ping_a_container() {
+        let (mut wing, pipe) = pipe_console();
+        let request = wing.fetch_node_data(9).unwrap();
+        wing.ping().unwrap();
+
+        // the current node is a container, so the ping is answered with each child's data
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 9, 0x01, 0xde,
+                       0xd7, 0, 0, 0, 10, 0x02, 0xd7, 0, 0, 0, 11, 0x03, 0xd7, 0, 0, 0, 12, 0x04, 0xde,
+                       0xd7, 0, 0, 0, 13, 0x05]);
+        assert!(matches!(wing.try_read().unwrap(), Some(WingResponse::NodeData(_, 13, _))));
+        assert!(wing.try_read().unwrap().is_none());
+        assert_eq!(request.wait_timeout(Duration::ZERO).unwrap().unwrap().get_int(), 1);
+        assert!(wing.health().ping_pending.is_none());
+    }
+
+    #[test]
+    fn stalled_pings() {
+        let (mut wing, pipe) = pipe_console_with(None, Some((Duration::from_millis(10), Duration::from_secs(60))));
+        pipe.take_sent();
+
+        // an unanswered ping isn't followed by more
+        assert!(wing.read_timeout(Duration::from_millis(100)).unwrap().is_none());
+        assert_eq!(pipe.take_sent(), [0xdc]);
+
+        pipe.receive(&[0xdf, 0xd1, 0xde]);
+        assert!(wing.read_timeout(Duration::from_millis(100)).unwrap().is_none());
+        assert_eq!(pipe.take_sent(), [0xdc]);
+    }
+
+    #[test]
+    fn stall_and_recover() {
+        let (mut wing, pipe) = pipe_console_with(None, Some((Duration::from_secs(60), Duration::from_millis(50))));
+
+        assert!(matches!(wing.read_timeout(Duration::from_secs(1)), Err(Error::Stalled(_))));
+        // reported once, after which reading carries on
+        assert!(wing.read_timeout(Duration::from_millis(100)).unwrap().is_none());
+
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03]);
+        assert!(matches!(wing.read_timeout(Duration::from_secs(1)).unwrap(), Some(WingResponse::NodeData(_, 5, _))));
+        assert!(wing.health().last_received.elapsed() < Duration::from_millis(50));
+
+        // and a stall after recovering is reported again
+        assert!(matches!(wing.read_timeout(Duration::from_secs(1)), Err(Error::Stalled(_))));
+    }
+}
@@ -38,16 +38,53 @@
 //! WingConsole wing = WingConsole::connect(None);
 //! ```
 //!
-//! There is also `WingConsole::scan()` which can be used to scan for Wing mixers.
+//! There is also `WingConsole::scan()` which can be used to scan for Wing mixers. It
+//! broadcasts on the subnet of each local network interface (see `NetInterface::all()`), and
+//! `DiscoveryInfo::interface` says which one each mixer was found on. On platforms where the
+//! interfaces can't be listed, it broadcasts to 255.255.255.255 instead. To keep an up to date
+//! list of the mixers on the network, use a `DiscoveryMonitor`, which reports each one that
+//! appears, changes or disappears.
+//!
+//! Broadcasts don't cross routers, VLANs or VPNs. To find a mixer on another subnet, use
+//! `WingConsole::scan_hosts()` to probe a list of addresses, or `WingConsole::scan_cidr()` to
+//! sweep a range like `10.0.5.0/24`. The probes are sent one at a time, a couple of
+//! milliseconds apart.
+//!
+//! Each `DiscoveryInfo` has the mixer's `WingModel`, and its firmware parsed into a
+//! `FirmwareVersion`, which can be compared to check whether the mixer has a feature:
+//!
+//! ```ignore
+//! if info.firmware_version >= Some(FirmwareVersion::new(3, 1, 0)) { ... }
+//! ```
+//!
+//! To test without a mixer, a `DiscoveryResponder` answers probes the way a mixer does.
+//!
+//! To use another port, bind to a particular interface, or set timeouts and the keep alive
+//! interval, use `WingConsole::builder()`, which returns a `WingConsoleBuilder`.
 //!
 //! ### Communication Model
 //!
 //! - `WingConsole::read()` will block and return you messages from the Wing mixer as they come in.
+//!   Use `WingConsole::read_timeout()` to limit how long it waits, or `WingConsole::try_read()`
+//!   to not wait at all; both return `Ok(None)` if no message arrived.
 //!
 //! - If the device is modified either physically or via another user of the API, the Wing device
 //!   sends unsolicited `WingResponse::NodeData(channel, id, data)` messages. The `channel`
-//!   parameter will always be the same unless you are using the metering API, which **libwing**
-//!   does not implement yet.
+//!   parameter is the native protocol channel the data arrived on, which for `read()` is 1,
+//!   `Channel::ControlEngine`, or -1 for anything received before the mixer selected a channel.
+//!
+//! - Meters are subscribed to with `WingConsole::subscribe_meters()`, which returns a
+//!   `MeterStream`. Meter data is sent by the mixer over UDP, so it never shows up in `read()`;
+//!   call `MeterStream::read()` (usually on its own thread) to get each `MeterFrame`, with the
+//!   levels in dB, or `MeterStream::read_timeout()` and `try_read()` to not wait forever.
+//!   Subscriptions expire after a few seconds, so `WingConsole::read()` renews them as long as
+//!   it is being called, until the `MeterStream` is dropped. Nothing else renews them: if the
+//!   console isn't read, the meter data stops.
+//!
+//! - The native protocol multiplexes 14 channels over one connection. `read()` and all of the
+//!   request and set functions use `Channel::ControlEngine`. To use another channel, send tokens
+//!   on it with `WingConsole::send()` and read its responses with `WingConsole::read_channel()`.
+//!   Each channel is queued separately, so responses on one never show up when reading another.
 //!
 //! - You can request properties from the Wing device using `WingConsole.request_node_data()`,
 //!   which will result in a `WingResponse::NodeData` being sent if your request was for a valid
@@ -61,21 +98,116 @@
 //!   schema. Again, unsolicited messages may be sent, so you may need to filter for your specific
 //!   NodeDef. After the NodeDef is sent (or not), the Wing device will send a `WingResponse::RequestEnd`
 //!
+//! - To have the library match responses to requests for you, use
+//!   `WingConsole::fetch_node_data()` and `WingConsole::fetch_node_definitions()`. They return
+//!   a `PendingRequest` that gets exactly the data or definitions sent in answer to it, and not
+//!   unsolicited messages; those, and the `RequestEnd`, are kept from `read()`. Any number can
+//!   be in flight at once. Get the reply with `WingConsole::wait()`, or with
+//!   `PendingRequest::wait_timeout()` when another thread is reading.
+//!
+//! - For the common case of asking for one thing and waiting for the answer, use
+//!   `WingConsole::get()`, `get_by_name()` or `get_children_defs()`. These return
+//!   `Error::NotFound` if the mixer has no such node, and `Error::Timeout` if it doesn't answer
+//!   in time.
+//!
 //! - You can set properties using the `WingConsole::set_*()` functions. These do not send any
 //!   response back.
 //!
+//! - Whatever the connection can't take right away is queued and sent later, in order. If the
+//!   mixer takes nothing for 5 seconds, sending returns `Error::Timeout`, and if 1 MiB is
+//!   waiting, it returns `Error::QueueFull`.
+//!
+//! - `WingConsole::split()` gives you a `WingReader` and a `WingWriter`, so one thread can block
+//!   in `WingReader::read()` while others send. `WingWriter` can be cloned and has all of the
+//!   request, set and navigation functions. Splitting needs `Transport::try_clone()`, which
+//!   `TcpTransport` has; other transports can leave it out if they are never split.
+//!
+//! - To watch the quality of the connection, turn on `WingConsoleBuilder::health_check()`. The
+//!   mixer is then pinged regularly while `read()` is called, `WingConsole::health()` gives the
+//!   round trip time and when something was last received, and `read()` returns
+//!   `Error::Stalled` if the mixer goes quiet for too long, well before TCP would notice. That
+//!   is reported once per silence; reading again keeps waiting in case the mixer comes back.
+//!   A ping asks for the current node's data; everything that arrives until its answer is
+//!   complete is taken as the answer, and not returned by `read()`.
+//!
+//! - A `WingConsole` is finished once its connection drops. `ReconnectingWingConsole` instead
+//!   connects again by itself, and its `read()` returns `WingEvent`s: the responses, plus a
+//!   `ConnectionEvent` whenever it is connecting, failed to connect, connected, lost or
+//!   resynced. Properties given to `ReconnectingWingConsole::watch()` are requested again on
+//!   every connection, and its meter subscriptions are sent again. `with_connector()` makes it
+//!   work over any transport.
+//!
+//! - Strings are sent and received as UTF-8, and their lengths are counted in bytes, not
+//!   characters. The protocol can carry at most `MAX_STRING_LEN` (256) bytes, and many properties
+//!   allow fewer (see `WingNodeDef::max_string_len`). `set_string()` returns
+//!   `Error::StringTooLong` instead of sending a string that is over either limit. The mixer's
+//!   own screens may not be able to show every character, so stick to ASCII if the text needs
+//!   to look right on the console. Received strings that aren't valid UTF-8 have the offending
+//!   bytes replaced with U+FFFD.
+//!
+//! - The native protocol also carries tree navigation and request tokens (select a child by
+//!   index, go to the root, go up, click, step, and data/definition requests). When one of these
+//!   is received it is returned as the matching `WingResponse` variant, so `read()` may return
+//!   more than just `NodeData`, `NodeDef` and `RequestEnd`. For the variants that act on the
+//!   current node, the id of the last node selected by id is given (0 after `GotoRoot`).
+//!
+//! - Besides addressing nodes by id, you can walk the tree the way the mixer's own controls do.
+//!   `WingConsole::goto_root()`, `go_up()`, `select_index()` and `select_id()` move the current
+//!   node; `step()` and `click()` act on it, like turning an encoder or pressing a button; and
+//!   `request_current_node_data()` and `request_current_node_definition()` request it. This lets
+//!   you reach nodes that have no name or whose id you don't know.
+//!
+//! - `WingConsole` does its protocol work with `WingCodec`, which does no I/O of its own. If you
+//!   want to talk to the mixer from your own event loop or over some other transport, feed the
+//!   received bytes to `WingCodec::feed()`, take responses out with `WingCodec::decode()`, and
+//!   write out the bytes produced by the `WingCodec::encode_*()` functions. Or, to keep using
+//!   `WingConsole` but over something other than TCP, implement `Transport` and connect with
+//!   `WingConsole::from_transport()`.
+//!
+//! - With the `async` feature enabled, `AsyncWingConsole` offers the same reads, requests and
+//!   sets as `async fn`s on a tokio `TcpStream`, or on any other tokio stream with
+//!   `AsyncWingConsole::from_stream()`. It needs to run inside a tokio runtime.
+//!
 
 
+mod builder;
+mod codec;
+mod discovery;
+mod health;
 mod console;
+#[cfg(feature = "async")]
+mod async_console;
+mod meters;
+mod reconnect;
+mod request;
 mod node;
 mod ffi;
 mod propmap;
+mod transport;
+#[cfg(test)]
+mod testing;
 
-pub use console::{WingConsole, DiscoveryInfo};
-pub use node::{WingNodeDef, WingNodeData, NodeType, NodeUnit};
+pub use builder::WingConsoleBuilder;
+pub use codec::{WingCodec, Token, Channel, MAX_STRING_LEN};
+pub use console::{WingConsole, WingReader, WingWriter};
+pub use discovery::{DiscoveryInfo, WingModel, FirmwareVersion, DiscoveryMonitor, DiscoveryEvent, DiscoveryResponder, NetInterface};
+#[cfg(feature = "async")]
+pub use async_console::AsyncWingConsole;
+pub use health::Health;
+pub use request::PendingRequest;
+pub use reconnect::{ReconnectingWingConsole, ConnectionEvent, WingEvent};
+pub use transport::{Transport, TcpTransport};
+pub use meters::{MeterSource, MeterStream, MeterFrame, StripMeters};
+pub use node::{WingNodeDef, WingNodeData, NodeType, NodeUnit, StringEnumItem, FloatEnumItem};
 
 type Result<T> = std::result::Result<T, Error>;
 
+// Locks `mutex` even if another thread panicked while holding it. Nothing here leaves shared
+// state half updated when it panics, so what the lock guards is still whole.
+pub(crate) fn lock<T>(mutex: &std::sync::Mutex<T>) -> std::sync::MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("IO error: {0}")]
@@ -86,10 +218,52 @@ pub enum Error {
     ConnectionError,
     #[error("Failed to discover Wing console")]
     DiscoveryError,
+    #[error("Timed out")]
+    Timeout,
+    #[error("Too much is waiting to be sent")]
+    QueueFull,
+    #[error("Invalid address range, expected an IPv4 range like 10.0.5.0/24 no wider than /16: {0}")]
+    InvalidCidr(String),
+    #[error("Invalid firmware version: {0}")]
+    InvalidFirmwareVersion(String),
+    #[error("Discovery reply fields can not contain commas: {0}")]
+    InvalidReplyField(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Nothing received from the mixer for {0:?}")]
+    Stalled(std::time::Duration),
+    #[error("Node indexes start at 1")]
+    InvalidNodeIndex,
+    #[error("Node names can not be empty")]
+    EmptyNodeName,
+    #[error("Invalid channel: {0}")]
+    InvalidChannel(u8),
+    #[error("Invalid meter source: {0:?}")]
+    InvalidMeterSource(MeterSource),
+    #[error("String of {0} bytes can not be sent, the limit is {1} bytes")]
+    StringTooLong(usize, usize),
+    #[error("Invalid node definition at byte {offset}: {reason}")]
+    NodeDefDecode { offset: usize, reason: &'static str },
+    #[error("Can not encode node definition: {0}")]
+    NodeDefEncode(&'static str),
 }
 
 pub enum WingResponse {
     RequestEnd,
     NodeDef(WingNodeDef),
     NodeData(i8, i32, WingNodeData),
+    /// A child of the current node was selected by its 1-based index.
+    NodeIndex(u16),
+    /// The node with this id was clicked.
+    Click(i32),
+    /// The value of the node with this id was stepped by the given amount.
+    Step(i32, i8),
+    /// The current node was reset to the root of the tree.
+    GotoRoot,
+    /// The current node was moved up to its parent.
+    GoUp,
+    /// The data of the node with this id was requested.
+    DataRequest(i32),
+    /// The definition of the node with this id was requested.
+    DefinitionRequest(i32),
 }
@@ -0,0 +1,315 @@
+use std::net::UdpSocket;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error};
+use crate::codec::{WingCodec, Channel};
+
+// The mixer stops sending meter data if a subscription isn't renewed within 5 seconds.
+const METER_RENEW_INTERVAL: Duration = Duration::from_secs(4);
+
+const VALUES_PER_STRIP: usize = 8;
+
+/// A set of meters that can be subscribed to. Numbers start at 1, as on the mixer.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub enum MeterSource {
+    /// Input channel 1 to 40
+    Channel(u8),
+    /// Aux input 1 to 8
+    Aux(u8),
+    /// Bus 1 to 16
+    Bus(u8),
+    /// Main 1 to 4
+    Main(u8),
+    /// Matrix 1 to 8
+    Matrix(u8),
+}
+
+impl MeterSource {
+    fn code(self) -> Result<[u8; 2]> {
+        let (code, number, count) = match self {
+            MeterSource::Channel(n) => (0xa0, n, 40),
+            MeterSource::Aux(n)     => (0xa1, n, 8),
+            MeterSource::Bus(n)     => (0xa2, n, 16),
+            MeterSource::Main(n)    => (0xa3, n, 4),
+            MeterSource::Matrix(n)  => (0xa4, n, 8),
+        };
+        if number == 0 || number > count {
+            return Err(Error::InvalidMeterSource(self));
+        }
+        Ok([code, number - 1])
+    }
+}
+
+/// The meters of one strip, in dB. The dynamics and gate gains are the gain reduction they are
+/// applying, so 0 dB when they are doing nothing.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct StripMeters {
+    pub input:         [f32; 2],
+    pub output:        [f32; 2],
+    pub gate_key:      f32,
+    pub gate_gain:     f32,
+    pub dynamics_key:  f32,
+    pub dynamics_gain: f32,
+}
+
+/// One packet of meter data, with the meters of every subscribed source in the order they
+/// were subscribed.
+#[derive(Clone, PartialEq, Debug)]
+pub struct MeterFrame {
+    pub meters: Vec<(MeterSource, StripMeters)>,
+}
+
+// What the console needs to keep a subscription alive.
+pub(crate) struct MeterSubscription {
+    request: Vec<u8>,
+    renewed: Instant,
+    alive:   Weak<()>,
+}
+
+impl MeterSubscription {
+    pub(crate) fn request(&self) -> &[u8] {
+        &self.request
+    }
+
+    // Returns the bytes to send if the subscription is due for renewal.
+    pub(crate) fn renewal(&mut self) -> Option<&[u8]> {
+        if self.renewed.elapsed() >= METER_RENEW_INTERVAL {
+            self.renewed = Instant::now();
+            Some(&self.request)
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn until_renewal(&self) -> Duration {
+        METER_RENEW_INTERVAL.saturating_sub(self.renewed.elapsed())
+    }
+
+    pub(crate) fn is_alive(&self) -> bool {
+        self.alive.strong_count() > 0
+    }
+
+    // A copy for another connection, which sends the request as it takes it.
+    pub(crate) fn restart(&self) -> MeterSubscription {
+        MeterSubscription {
+            request: self.request.clone(),
+            renewed: Instant::now(),
+            alive:   self.alive.clone(),
+        }
+    }
+}
+
+/// Receives the meter data of one subscription, made with `WingConsole::subscribe_meters()`.
+///
+/// Meter data arrives over UDP, separately from the console's other responses. The console
+/// renews the subscription from `WingConsole::read()`, so something has to keep reading the
+/// console, or the mixer stops sending after about 5 seconds. Renewal stops once the
+/// `MeterStream` is dropped.
+pub struct MeterStream {
+    socket:    UdpSocket,
+    report_id: u32,
+    sources:   Vec<MeterSource>,
+    _alive:    Arc<()>,
+}
+
+impl MeterStream {
+    // Binds the UDP socket the meter data will be sent to, and builds the subscription request
+    // that asks for it.
+    pub(crate) fn new(sources: &[MeterSource]) -> Result<(MeterStream, MeterSubscription)> {
+        let socket = UdpSocket::bind("0.0.0.0:0")?;
+        let port = socket.local_addr()?.port();
+        let report_id = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.subsec_nanos() ^ port as u32)
+            .unwrap_or(port as u32);
+
+        let mut request = vec![0xd3];
+        request.extend_from_slice(&port.to_be_bytes());
+        request.push(0xd4);
+        request.extend_from_slice(&report_id.to_be_bytes());
+        for source in sources {
+            request.extend_from_slice(&source.code()?);
+        }
+        let mut buf = Vec::new();
+        WingCodec::encode_raw_on_channel(Channel::Meters, &request, &mut buf)?;
+
+        let alive = Arc::new(());
+        let subscription = MeterSubscription {
+            request: buf,
+            renewed: Instant::now(),
+            alive: Arc::downgrade(&alive),
+        };
+        let stream = MeterStream {
+            socket,
+            report_id,
+            sources: sources.to_vec(),
+            _alive: alive,
+        };
+        Ok((stream, subscription))
+    }
+
+    pub fn sources(&self) -> &[MeterSource] {
+        &self.sources
+    }
+
+    /// Blocks until the next packet of meter data arrives.
+    pub fn read(&mut self) -> Result<MeterFrame> {
+        self.socket.set_read_timeout(None)?;
+        loop {
+            if let Some(frame) = self.receive()? {
+                return Ok(frame);
+            }
+        }
+    }
+
+    /// Like `read()`, but gives up and returns `Ok(None)` if nothing arrives within `timeout`.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<MeterFrame>> {
+        let Some(deadline) = Instant::now().checked_add(timeout) else {
+            return self.read().map(Some);
+        };
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return self.try_read();
+            }
+            self.socket.set_read_timeout(Some(remaining))?;
+            match self.receive() {
+                Ok(Some(frame)) => return Ok(Some(frame)),
+                Ok(None) => {}
+                Err(Error::Io(e)) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {}
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// Returns the next packet of meter data if one has already arrived, or `Ok(None)` without
+    /// waiting.
+    pub fn try_read(&mut self) -> Result<Option<MeterFrame>> {
+        self.socket.set_nonblocking(true)?;
+        let ret = loop {
+            match self.receive() {
+                Ok(Some(frame)) => break Ok(Some(frame)),
+                Ok(None) => {}
+                Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::WouldBlock => break Ok(None),
+                Err(e) => break Err(e),
+            }
+        };
+        self.socket.set_nonblocking(false)?;
+        ret
+    }
+
+    // Receives one packet, returning None if it was for another subscription.
+    fn receive(&mut self) -> Result<Option<MeterFrame>> {
+        let mut buf = [0u8; 8192];
+        let (n, _) = self.socket.recv_from(&mut buf)?;
+        decode_frame(self.report_id, &self.sources, &buf[..n])
+    }
+}
+
+// Decodes a packet of meter data, which starts with the report id of the subscription it is
+// for. Packets for other subscriptions can't arrive on our port, but give None to be sure.
+fn decode_frame(report_id: u32, sources: &[MeterSource], packet: &[u8]) -> Result<Option<MeterFrame>> {
+    if packet.len() < 4 {
+        return Err(Error::InvalidData);
+    }
+    if packet[..4] != report_id.to_be_bytes() {
+        return Ok(None);
+    }
+    let data = &packet[4..];
+    if data.len() < sources.len() * VALUES_PER_STRIP * 2 {
+        return Err(Error::InvalidData);
+    }
+    let db = |i: usize| i16::from_be_bytes([data[i * 2], data[i * 2 + 1]]) as f32 / 256.0;
+    let meters = sources.iter().enumerate().map(|(n, source)| {
+        let i = n * VALUES_PER_STRIP;
+        (*source, StripMeters {
+            input:         [db(i), db(i + 1)],
+            output:        [db(i + 2), db(i + 3)],
+            gate_key:      db(i + 4),
+            gate_gain:     db(i + 5),
+            dynamics_key:  db(i + 6),
+            dynamics_gain: db(i + 7),
+        })
+    }).collect();
+    Ok(Some(MeterFrame { meters }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A packet for `report_id` with the given raw values.
+    fn packet(report_id: u32, values: &[i16]) -> Vec<u8> {
+        let mut packet = report_id.to_be_bytes().to_vec();
+        for v in values {
+            packet.extend_from_slice(&v.to_be_bytes());
+        }
+        packet
+    }
+
+    #[test]
+    fn source_codes() {
+        assert_eq!(MeterSource::Channel(1).code().unwrap(), [0xa0, 0]);
+        assert_eq!(MeterSource::Channel(40).code().unwrap(), [0xa0, 39]);
+        assert_eq!(MeterSource::Aux(8).code().unwrap(), [0xa1, 7]);
+        assert_eq!(MeterSource::Bus(16).code().unwrap(), [0xa2, 15]);
+        assert_eq!(MeterSource::Main(4).code().unwrap(), [0xa3, 3]);
+        assert_eq!(MeterSource::Matrix(8).code().unwrap(), [0xa4, 7]);
+
+        for source in [MeterSource::Channel(0), MeterSource::Channel(41), MeterSource::Aux(9),
+                       MeterSource::Bus(17), MeterSource::Main(5), MeterSource::Matrix(9)] {
+            assert!(matches!(source.code(), Err(Error::InvalidMeterSource(s)) if s == source));
+        }
+    }
+
+    #[test]
+    fn frame() {
+        let sources = [MeterSource::Channel(1), MeterSource::Main(1)];
+        let mut values = vec![0i16; 16];
+        values[0] = -256;           // -1 dB
+        values[1] = -6 * 256 - 128; // -6.5 dB
+        values[2] = 12 * 256;       // +12 dB
+        values[15] = i16::MIN;      // -128 dB
+
+        let frame = decode_frame(7, &sources, &packet(7, &values)).unwrap().unwrap();
+        assert_eq!(frame.meters.len(), 2);
+        let (source, ch1) = frame.meters[0];
+        assert_eq!(source, MeterSource::Channel(1));
+        assert_eq!(ch1.input, [-1.0, -6.5]);
+        assert_eq!(ch1.output, [12.0, 0.0]);
+        let (source, main) = frame.meters[1];
+        assert_eq!(source, MeterSource::Main(1));
+        assert_eq!(main.dynamics_gain, -128.0);
+    }
+
+    #[test]
+    fn bad_packets() {
+        let sources = [MeterSource::Bus(3)];
+        assert!(decode_frame(7, &sources, &packet(8, &[0; 8])).unwrap().is_none());
+        assert!(matches!(decode_frame(7, &sources, &packet(7, &[0; 7])), Err(Error::InvalidData)));
+        assert!(matches!(decode_frame(7, &sources, &[0, 0, 7]), Err(Error::InvalidData)));
+        // odd trailing bytes are ignored
+        let mut long = packet(7, &[0; 8]);
+        long.push(0);
+        assert!(decode_frame(7, &sources, &long).unwrap().is_some());
+    }
+
+    #[test]
+    fn stream_timeouts() {
+        let (mut stream, _subscription) = MeterStream::new(&[MeterSource::Main(1)]).unwrap();
+        assert!(stream.try_read().unwrap().is_none());
+        assert!(stream.read_timeout(Duration::from_millis(20)).unwrap().is_none());
+
+        let port = stream.socket.local_addr().unwrap().port();
+        let mixer = UdpSocket::bind("127.0.0.1:0").unwrap();
+        mixer.send_to(&packet(stream.report_id ^ 1, &[0; 8]), ("127.0.0.1", port)).unwrap();
+        mixer.send_to(&packet(stream.report_id, &[256; 8]), ("127.0.0.1", port)).unwrap();
+        let frame = stream.read_timeout(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(frame.meters[0].1.input, [1.0, 1.0]);
+
+        // a timeout too long to have a deadline waits as read() does
+        mixer.send_to(&packet(stream.report_id, &[0; 8]), ("127.0.0.1", port)).unwrap();
+        assert!(stream.read_timeout(Duration::MAX).unwrap().is_some());
+    }
+}
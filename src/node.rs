@@ -1,39 +1,110 @@
-#[repr(C)]
+use crate::{Result, Error};
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum NodeType {
-    Node = 0,
-    LinearFloat = 1,
-    LogarithmicFloat = 2,
-    FaderLevel = 3,
-    Integer = 4,
-    StringEnum = 5,
-    FloatEnum = 6,
-    String = 7,
+    Node,
+    LinearFloat,
+    LogarithmicFloat,
+    FaderLevel,
+    Integer,
+    StringEnum,
+    FloatEnum,
+    String,
+    /// A type this version of libwing doesn't know about, with its 4 bit code.
+    Unknown(u8),
+}
+
+impl From<u8> for NodeType {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => NodeType::Node,
+            1 => NodeType::LinearFloat,
+            2 => NodeType::LogarithmicFloat,
+            3 => NodeType::FaderLevel,
+            4 => NodeType::Integer,
+            5 => NodeType::StringEnum,
+            6 => NodeType::FloatEnum,
+            7 => NodeType::String,
+            _ => NodeType::Unknown(code),
+        }
+    }
+}
+
+impl From<NodeType> for u8 {
+    fn from(node_type: NodeType) -> Self {
+        match node_type {
+            NodeType::Node             => 0,
+            NodeType::LinearFloat      => 1,
+            NodeType::LogarithmicFloat => 2,
+            NodeType::FaderLevel       => 3,
+            NodeType::Integer          => 4,
+            NodeType::StringEnum       => 5,
+            NodeType::FloatEnum        => 6,
+            NodeType::String           => 7,
+            NodeType::Unknown(code)    => code,
+        }
+    }
 }
 
-#[repr(C)]
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum NodeUnit {
-    None = 0,
-    Db = 1,
-    Percent = 2,
-    Milliseconds = 3,
-    Hertz = 4,
-    Meters = 5,
-    Seconds = 6,
-    Octaves = 7,
+    None,
+    Db,
+    Percent,
+    Milliseconds,
+    Hertz,
+    Meters,
+    Seconds,
+    Octaves,
+    /// A unit this version of libwing doesn't know about, with its 4 bit code.
+    Unknown(u8),
+}
+
+impl From<u8> for NodeUnit {
+    fn from(code: u8) -> Self {
+        match code {
+            0 => NodeUnit::None,
+            1 => NodeUnit::Db,
+            2 => NodeUnit::Percent,
+            3 => NodeUnit::Milliseconds,
+            4 => NodeUnit::Hertz,
+            5 => NodeUnit::Meters,
+            6 => NodeUnit::Seconds,
+            7 => NodeUnit::Octaves,
+            _ => NodeUnit::Unknown(code),
+        }
+    }
+}
+
+impl From<NodeUnit> for u8 {
+    fn from(unit: NodeUnit) -> Self {
+        match unit {
+            NodeUnit::None         => 0,
+            NodeUnit::Db           => 1,
+            NodeUnit::Percent      => 2,
+            NodeUnit::Milliseconds => 3,
+            NodeUnit::Hertz        => 4,
+            NodeUnit::Meters       => 5,
+            NodeUnit::Seconds      => 6,
+            NodeUnit::Octaves      => 7,
+            NodeUnit::Unknown(code) => code,
+        }
+    }
 }
 
+#[derive(Debug, PartialEq)]
 pub struct StringEnumItem {
     pub item: String,
     pub long_item: String,
 }
 
+#[derive(Debug, PartialEq)]
 pub struct FloatEnumItem {
     pub item: f32,
     pub long_item: String,
 }
 
+#[derive(Debug)]
 pub struct WingNodeDef {
     pub id: i32,
     pub parent_id: i32,
@@ -54,51 +125,64 @@ pub struct WingNodeDef {
     pub raw: Vec<u8>,
 }
 
-impl WingNodeDef {
-    pub fn from_bytes(raw: &[u8]) -> Self {
-        let mut i = 0;
-
-        let parent_id = i32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]);
-        i += 4;
-        let id = i32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]);
-        i += 4;
-        let index = u16::from_be_bytes([raw[i], raw[i+1]]);
-        i += 2;
-        let name_len = raw[i];
-        i += 1;
-        let name = String::from_utf8(raw[i..i+name_len as usize].to_vec()).unwrap();
-        i += name_len as usize;
-        let long_name_len = raw[i];
-        i += 1;
-        let long_name = String::from_utf8(raw[i..i+long_name_len as usize].to_vec()).unwrap();
-        i += long_name_len as usize;
-        let flags = u16::from_be_bytes([raw[i], raw[i+1]]);
-        i += 2;
-
-        let node_type = match (flags >> 4) & 0x0F {
-            0 => NodeType::Node,
-            1 => NodeType::LinearFloat,
-            2 => NodeType::LogarithmicFloat,
-            3 => NodeType::FaderLevel,
-            4 => NodeType::Integer,
-            5 => NodeType::StringEnum,
-            6 => NodeType::FloatEnum,
-            7 => NodeType::String,
-            _ => NodeType::Node,
-        };
+// Reads the fields of a node definition, turning a short buffer into an error that says what
+// was being read and where.
+struct DefReader<'a> {
+    raw: &'a [u8],
+    i:   usize,
+}
 
-        let unit = match flags & 0x0F {
-            0 => NodeUnit::None,
-            1 => NodeUnit::Db,
-            2 => NodeUnit::Percent,
-            3 => NodeUnit::Milliseconds,
-            4 => NodeUnit::Hertz,
-            5 => NodeUnit::Meters,
-            6 => NodeUnit::Seconds,
-            7 => NodeUnit::Octaves,
-            _ => NodeUnit::None,
-        };
+impl<'a> DefReader<'a> {
+    fn take(&mut self, n: usize, what: &'static str) -> Result<&'a [u8]> {
+        if self.raw.len() - self.i < n {
+            return Err(Error::NodeDefDecode { offset: self.i, reason: what });
+        }
+        let bytes = &self.raw[self.i..self.i + n];
+        self.i += n;
+        Ok(bytes)
+    }
+
+    fn u8(&mut self, what: &'static str) -> Result<u8> {
+        Ok(self.take(1, what)?[0])
+    }
+
+    fn u16(&mut self, what: &'static str) -> Result<u16> {
+        let b = self.take(2, what)?;
+        Ok(u16::from_be_bytes([b[0], b[1]]))
+    }
+
+    fn i32(&mut self, what: &'static str) -> Result<i32> {
+        let b = self.take(4, what)?;
+        Ok(i32::from_be_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    fn f32(&mut self, what: &'static str) -> Result<f32> {
+        Ok(f32::from_bits(self.i32(what)? as u32))
+    }
 
+    // a string preceded by its 8 bit length, decoded the same way as received string values
+    fn string(&mut self, what: &'static str) -> Result<String> {
+        let len = self.u8(what)? as usize;
+        Ok(String::from_utf8_lossy(self.take(len, what)?).into_owned())
+    }
+}
+
+impl WingNodeDef {
+    /// Parses a node definition as sent by the mixer. Types and units this version doesn't know
+    /// about come back as `NodeType::Unknown` and `NodeUnit::Unknown`, and for unknown types
+    /// any type specific data is skipped.
+    pub fn from_bytes(raw: &[u8]) -> Result<Self> {
+        let mut r = DefReader { raw, i: 0 };
+
+        let parent_id = r.i32("parent id")?;
+        let id = r.i32("id")?;
+        let index = r.u16("index")?;
+        let name = r.string("name")?;
+        let long_name = r.string("long name")?;
+        let flags = r.u16("flags")?;
+
+        let node_type = NodeType::from(((flags >> 4) & 0x0F) as u8);
+        let unit = NodeUnit::from((flags & 0x0F) as u8);
         let read_only = ((flags >> 9) & 0x01) != 0;
 
         let mut min_float      = Option::None;
@@ -111,69 +195,53 @@ impl WingNodeDef {
         let mut float_enum     = Option::None;
 
         match node_type {
-            NodeType::Node | NodeType::FaderLevel => { }
+            NodeType::Node | NodeType::FaderLevel | NodeType::Unknown(_) => { }
             NodeType::String => {
-                max_string_len = Some(u16::from_be_bytes([raw[i], raw[i+1]]));
-                //i += 2;
+                max_string_len = Some(r.u16("max string length")?);
             }
-            NodeType::LinearFloat | 
+            NodeType::LinearFloat |
                 NodeType::LogarithmicFloat => {
-                    min_float = Some(f32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]));
-                    i += 4;
-                    max_float = Some(f32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]));
-                    i += 4;
-                    steps = Some(i32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]));
-                    //i += 4;
+                    min_float = Some(r.f32("minimum")?);
+                    max_float = Some(r.f32("maximum")?);
+                    steps = Some(r.i32("steps")?);
                 }
             NodeType::Integer => {
-                min_int = Some(i32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]));
-                i += 4;
-                max_int = Some(i32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]));
-                //i += 4;
+                min_int = Some(r.i32("minimum")?);
+                max_int = Some(r.i32("maximum")?);
             }
             NodeType::StringEnum => {
-                let num = u16::from_be_bytes([raw[i], raw[i+1]]);
-                i += 2;
+                let num = r.u16("enum item count")?;
+                let mut items = Vec::new();
                 for _ in 0..num {
-                    let item_len = raw[i] as usize;
-                    i += 1;
-                    let item = String::from_utf8(raw[i..i+item_len].to_vec()).unwrap();
-                    i += item_len;
-                    let long_item_len = raw[i] as usize;
-                    i += 1;
-                    let long_item = String::from_utf8(raw[i..i+long_item_len].to_vec()).unwrap();
-                    i += long_item_len;
-                    if string_enum.is_none() {
-                        string_enum = Some(Vec::new());
-                    }
-                    string_enum.as_mut().unwrap().push(StringEnumItem {
+                    let item = r.string("enum item")?;
+                    let long_item = r.string("enum long item")?;
+                    items.push(StringEnumItem {
                         item,
                         long_item,
                     });
                 }
+                if !items.is_empty() {
+                    string_enum = Some(items);
+                }
             }
             NodeType::FloatEnum => {
-                let num = u16::from_be_bytes([raw[i], raw[i+1]]);
-                i += 2;
+                let num = r.u16("enum item count")?;
+                let mut items = Vec::new();
                 for _ in 0..num {
-                    let item = f32::from_be_bytes([raw[i], raw[i+1], raw[i+2], raw[i+3]]);
-                    i += 4;
-                    let long_item_len = raw[i] as usize;
-                    i += 1;
-                    let long_item = String::from_utf8(raw[i..i+long_item_len].to_vec()).unwrap();
-                    i += long_item_len;
-                    if float_enum.is_none() {
-                        float_enum = Some(Vec::new());
-                    }
-                    float_enum.as_mut().unwrap().push(FloatEnumItem {
+                    let item = r.f32("enum item")?;
+                    let long_item = r.string("enum long item")?;
+                    items.push(FloatEnumItem {
                         item,
                         long_item,
                     });
                 }
+                if !items.is_empty() {
+                    float_enum = Some(items);
+                }
             }
         }
 
-        WingNodeDef {
+        Ok(WingNodeDef {
             id,
             parent_id,
             index,
@@ -191,31 +259,121 @@ impl WingNodeDef {
             string_enum,
             float_enum,
             raw: raw.to_vec(),
-        }
+        })
     }
 }
 
-impl Clone for WingNodeDef {
-    fn clone(&self) -> Self {
-        let mut string_enum = None;
-        if self.string_enum.is_some() {
-            string_enum = Some(self.string_enum.as_ref().unwrap().iter().map(|item| {
-                StringEnumItem {
-                    item: item.item.clone(),
-                    long_item: item.long_item.clone(),
-                }
-            }).collect::<Vec<_>>());
+impl WingNodeDef {
+    /// Encodes the definition in the wire format `from_bytes()` reads, built from the struct
+    /// fields rather than `raw`. Flag bits other than the type, unit and read-only flag are
+    /// not kept by `from_bytes()`, so they are sent as zero.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        fn string(buf: &mut Vec<u8>, s: &str, what: &'static str) -> Result<()> {
+            let len = u8::try_from(s.len()).map_err(|_| Error::NodeDefEncode(what))?;
+            buf.push(len);
+            buf.extend_from_slice(s.as_bytes());
+            Ok(())
+        }
+        fn count(buf: &mut Vec<u8>, n: usize) -> Result<()> {
+            let n = u16::try_from(n).map_err(|_| Error::NodeDefEncode("too many enum items"))?;
+            buf.extend_from_slice(&n.to_be_bytes());
+            Ok(())
+        }
+        fn field<T>(value: Option<T>, what: &'static str) -> Result<T> {
+            value.ok_or(Error::NodeDefEncode(what))
+        }
+
+        let type_code = u8::from(self.node_type);
+        let unit_code = u8::from(self.unit);
+        if type_code > 0x0f {
+            return Err(Error::NodeDefEncode("type code doesn't fit in 4 bits"));
+        }
+        if unit_code > 0x0f {
+            return Err(Error::NodeDefEncode("unit code doesn't fit in 4 bits"));
         }
 
-        let mut float_enum = None;
-        if self.float_enum.is_some() {
-            float_enum = Some(self.float_enum.as_ref().unwrap().iter().map(|item| {
-                FloatEnumItem {
-                    item: item.item,
-                    long_item: item.long_item.clone(),
+        let mut buf = Vec::new();
+        buf.extend_from_slice(&self.parent_id.to_be_bytes());
+        buf.extend_from_slice(&self.id.to_be_bytes());
+        buf.extend_from_slice(&self.index.to_be_bytes());
+        string(&mut buf, &self.name, "name is longer than 255 bytes")?;
+        string(&mut buf, &self.long_name, "long name is longer than 255 bytes")?;
+        let flags = (type_code as u16) << 4 | unit_code as u16 | (self.read_only as u16) << 9;
+        buf.extend_from_slice(&flags.to_be_bytes());
+
+        match self.node_type {
+            NodeType::Node | NodeType::FaderLevel | NodeType::Unknown(_) => { }
+            NodeType::String => {
+                buf.extend_from_slice(&field(self.max_string_len, "missing max_string_len")?.to_be_bytes());
+            }
+            NodeType::LinearFloat |
+                NodeType::LogarithmicFloat => {
+                    buf.extend_from_slice(&field(self.min_float, "missing min_float")?.to_be_bytes());
+                    buf.extend_from_slice(&field(self.max_float, "missing max_float")?.to_be_bytes());
+                    buf.extend_from_slice(&field(self.steps, "missing steps")?.to_be_bytes());
+                }
+            NodeType::Integer => {
+                buf.extend_from_slice(&field(self.min_int, "missing min_int")?.to_be_bytes());
+                buf.extend_from_slice(&field(self.max_int, "missing max_int")?.to_be_bytes());
+            }
+            NodeType::StringEnum => {
+                let items = self.string_enum.as_deref().unwrap_or_default();
+                count(&mut buf, items.len())?;
+                for item in items {
+                    string(&mut buf, &item.item, "enum item is longer than 255 bytes")?;
+                    string(&mut buf, &item.long_item, "enum long item is longer than 255 bytes")?;
+                }
+            }
+            NodeType::FloatEnum => {
+                let items = self.float_enum.as_deref().unwrap_or_default();
+                count(&mut buf, items.len())?;
+                for item in items {
+                    buf.extend_from_slice(&item.item.to_be_bytes());
+                    string(&mut buf, &item.long_item, "enum long item is longer than 255 bytes")?;
                 }
-            }).collect::<Vec<_>>());
+            }
         }
+        Ok(buf)
+    }
+}
+
+// `raw` is only a copy of the bytes the definition was parsed from, so it isn't compared.
+impl PartialEq for WingNodeDef {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id &&
+            self.parent_id == other.parent_id &&
+            self.index == other.index &&
+            self.name == other.name &&
+            self.long_name == other.long_name &&
+            self.node_type == other.node_type &&
+            self.unit == other.unit &&
+            self.read_only == other.read_only &&
+            self.min_float == other.min_float &&
+            self.max_float == other.max_float &&
+            self.steps == other.steps &&
+            self.min_int == other.min_int &&
+            self.max_int == other.max_int &&
+            self.max_string_len == other.max_string_len &&
+            self.string_enum == other.string_enum &&
+            self.float_enum == other.float_enum
+    }
+}
+
+impl Clone for WingNodeDef {
+    fn clone(&self) -> Self {
+        let string_enum = self.string_enum.as_ref().map(|items| items.iter().map(|item| {
+            StringEnumItem {
+                item: item.item.clone(),
+                long_item: item.long_item.clone(),
+            }
+        }).collect::<Vec<_>>());
+
+        let float_enum = self.float_enum.as_ref().map(|items| items.iter().map(|item| {
+            FloatEnumItem {
+                item: item.item,
+                long_item: item.long_item.clone(),
+            }
+        }).collect::<Vec<_>>());
 
         Self {
             id: self.id,
@@ -385,6 +543,7 @@ impl WingNodeDef {
                 NodeType::FaderLevel       => "fader level (float)",
                 NodeType::StringEnum       => "string enum",
                 NodeType::FloatEnum        => "float enum",
+                NodeType::Unknown(_)       => "unknown",
             }));
         if self.unit != NodeUnit::None {
             r.push_str(&format!("\nUnit:      {}",
@@ -416,10 +575,10 @@ impl WingNodeDef {
                 if let Some(max_string_len) = self.max_string_len { r.push_str(&format!("\nMaxLength: {}", max_string_len)); }
             }
             NodeType::StringEnum  => {
-                if self.string_enum.is_some() {
+                if let Some(items) = &self.string_enum {
                     r.push_str("\nItems:");
                     let mut first = true;
-                    for item in self.string_enum.as_ref().unwrap() {
+                    for item in items {
                         if first {
                             r.push_str(&format!("     {}", item.item));
                             first = false;
@@ -435,10 +594,10 @@ impl WingNodeDef {
                 }
             }
             NodeType::FloatEnum => {
-                if self.float_enum.is_some() {
+                if let Some(items) = &self.float_enum {
                     r.push_str("\nItems:");
                     let mut first = true;
-                    for item in self.float_enum.as_ref().unwrap() {
+                    for item in items {
                         if first {
                             r.push_str(&format!("     {}", item.item));
                             first = false;
@@ -485,6 +644,7 @@ impl WingNodeDef {
             NodeType::FaderLevel       => { json.insert("type", "fader level").unwrap(); }
             NodeType::StringEnum       => { json.insert("type", "string enum").unwrap(); }
             NodeType::FloatEnum        => { json.insert("type", "float enum").unwrap(); }
+            NodeType::Unknown(_)       => { json.insert("type", "unknown").unwrap(); }
         }
         match self.unit {
             NodeUnit::None         => { }
@@ -495,6 +655,7 @@ impl WingNodeDef {
             NodeUnit::Meters       => { json.insert("unit", "meters").unwrap(); }
             NodeUnit::Seconds      => { json.insert("unit", "seconds").unwrap(); }
             NodeUnit::Octaves      => { json.insert("unit", "octaves").unwrap(); }
+            NodeUnit::Unknown(_)   => { json.insert("unit", "unknown").unwrap(); }
         }
 
         if self.read_only {
@@ -517,8 +678,8 @@ impl WingNodeDef {
                 if let Some(max_string_len) = self.max_string_len { json.insert("maxstringlen", max_string_len).unwrap(); }
             }
             NodeType::StringEnum  => {
-                if self.string_enum.is_some() {
-                    json.insert("items", self.string_enum.as_ref().unwrap().iter().map(|item| {
+                if let Some(items) = &self.string_enum {
+                    json.insert("items", items.iter().map(|item| {
                         let mut j = jzon::object!{ "item": item.item.clone() };
                         if !item.long_item.is_empty() {
                             j.insert("longitem", item.long_item.clone()).unwrap();
@@ -528,8 +689,8 @@ impl WingNodeDef {
                 }
             }
             NodeType::FloatEnum => {
-                if self.float_enum.is_some() {
-                    json.insert("items", self.float_enum.as_ref().unwrap().iter().map(|item| {
+                if let Some(items) = &self.float_enum {
+                    json.insert("items", items.iter().map(|item| {
                         let mut j = jzon::object!{ "item": item.item };
                         if !item.long_item.is_empty() {
                             j.insert("longitem", item.long_item.clone()).unwrap();
@@ -543,3 +704,117 @@ impl WingNodeDef {
         json
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parent 1, id 2, index 0, name "ab", no long name, integer type, min -5, max 5
+    const INTEGER_DEF: &[u8] = &[
+        0, 0, 0, 1,  0, 0, 0, 2,  0, 0,  2, b'a', b'b',  0,  0x00, 0x40,
+        0xff, 0xff, 0xff, 0xfb,  0, 0, 0, 5,
+    ];
+
+    #[test]
+    fn truncated_def_is_an_error() {
+        assert!(WingNodeDef::from_bytes(INTEGER_DEF).is_ok());
+        for len in 0..INTEGER_DEF.len() {
+            match WingNodeDef::from_bytes(&INTEGER_DEF[..len]) {
+                Err(Error::NodeDefDecode { offset, .. }) => assert!(offset <= len),
+                _ => panic!("expected an error for {} bytes", len),
+            }
+        }
+    }
+
+    fn def(node_type: NodeType) -> WingNodeDef {
+        WingNodeDef {
+            id: 0x12df34,
+            parent_id: -7,
+            index: 3,
+            name: "name".to_string(),
+            long_name: "Long \u{7ff} Name".to_string(),
+            node_type,
+            unit: NodeUnit::Db,
+            read_only: true,
+            min_float: None,
+            max_float: None,
+            steps: None,
+            min_int: None,
+            max_int: None,
+            max_string_len: None,
+            string_enum: None,
+            float_enum: None,
+            raw: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn to_bytes_round_trip() {
+        let mut defs = vec![def(NodeType::Node), def(NodeType::FaderLevel), def(NodeType::Unknown(9))];
+
+        let mut d = def(NodeType::String);
+        d.max_string_len = Some(16);
+        defs.push(d);
+
+        for t in [NodeType::LinearFloat, NodeType::LogarithmicFloat] {
+            let mut d = def(t);
+            d.unit = NodeUnit::Hertz;
+            d.min_float = Some(20.0);
+            d.max_float = Some(20000.0);
+            d.steps = Some(-1);
+            defs.push(d);
+        }
+
+        let mut d = def(NodeType::Integer);
+        d.unit = NodeUnit::Unknown(0xc);
+        d.min_int = Some(i32::MIN);
+        d.max_int = Some(i32::MAX);
+        defs.push(d);
+
+        let mut d = def(NodeType::StringEnum);
+        d.string_enum = Some((0..300).map(|i| StringEnumItem {
+            item: i.to_string(),
+            long_item: if i % 2 == 0 { String::new() } else { "x".repeat(255) },
+        }).collect());
+        defs.push(d);
+        defs.push(def(NodeType::StringEnum));
+
+        let mut d = def(NodeType::FloatEnum);
+        d.float_enum = Some(vec![
+            FloatEnumItem { item: 0.5, long_item: "half".to_string() },
+            FloatEnumItem { item: -3.0, long_item: String::new() },
+        ]);
+        defs.push(d);
+
+        for d in defs {
+            let bytes = d.to_bytes().unwrap();
+            let parsed = WingNodeDef::from_bytes(&bytes).unwrap();
+            assert_eq!(parsed, d);
+            assert_eq!(parsed.raw, bytes);
+            assert_eq!(parsed.to_bytes().unwrap(), bytes);
+        }
+
+        assert_eq!(WingNodeDef::from_bytes(INTEGER_DEF).unwrap().to_bytes().unwrap(), INTEGER_DEF);
+    }
+
+    #[test]
+    fn to_bytes_errors() {
+        let mut d = def(NodeType::Integer);
+        assert!(matches!(d.to_bytes(), Err(Error::NodeDefEncode(_))));
+        d.min_int = Some(0);
+        d.max_int = Some(1);
+        assert!(d.to_bytes().is_ok());
+        d.name = "x".repeat(256);
+        assert!(matches!(d.to_bytes(), Err(Error::NodeDefEncode(_))));
+        assert!(matches!(def(NodeType::Unknown(16)).to_bytes(), Err(Error::NodeDefEncode(_))));
+    }
+
+    #[test]
+    fn unknown_type_and_unit() {
+        let mut raw = INTEGER_DEF[..16].to_vec();
+        raw[15] = 0xfa;
+        let def = WingNodeDef::from_bytes(&raw).unwrap();
+        assert_eq!(def.node_type, NodeType::Unknown(0xf));
+        assert_eq!(def.unit, NodeUnit::Unknown(0xa));
+    }
+}
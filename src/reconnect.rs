@@ -0,0 +1,376 @@
+use std::collections::{BTreeSet, VecDeque};
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error, WingResponse};
+use crate::builder::WingConsoleBuilder;
+use crate::console::WingConsole;
+use crate::meters::{MeterSource, MeterStream, MeterSubscription};
+use crate::transport::{Transport, TcpTransport};
+
+// How long to wait before trying again after a connection attempt fails.
+const RETRY_INTERVAL: Duration = Duration::from_secs(2);
+
+/// A change in the state of a `ReconnectingWingConsole`'s connection.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum ConnectionEvent {
+    /// A connection attempt is starting.
+    Connecting,
+    /// The connection attempt failed. The next one is made a couple of seconds later.
+    Failed,
+    /// The connection is up, and the watched properties are being requested again.
+    Connected,
+    /// The connection was lost. A new one will be attempted on the next `read()`.
+    Lost,
+    /// The data of every watched property has been received since connecting.
+    Resynced,
+}
+
+/// What `ReconnectingWingConsole::read()` returns.
+pub enum WingEvent {
+    Connection(ConnectionEvent),
+    Response(WingResponse),
+}
+
+/// A console connection that comes back by itself when the mixer reboots or the network drops.
+///
+/// Nothing happens until `read()` is called; it connects, and after a connection is lost it
+/// keeps trying again. Every time it connects, the data of each property given to `watch()`
+/// is requested and the meter subscriptions are sent again, so the application sees current
+/// values without doing anything itself.
+pub struct ReconnectingWingConsole<T: Transport = TcpTransport> {
+    connector: Box<dyn FnMut() -> Result<WingConsole<T>> + Send>,
+    console:   Option<WingConsole<T>>,
+    // when the next connection attempt may be made
+    retry_at:  Instant,
+    watched:   BTreeSet<i32>,
+    meters:    Vec<MeterSubscription>,
+    // RequestEnds still to come for the watched properties requested since connecting
+    resyncing: usize,
+    events:    VecDeque<WingEvent>,
+}
+
+impl ReconnectingWingConsole {
+    /// Creates a client for `host_or_ip`, or for the first mixer found by `WingConsole::scan()`
+    /// each time it connects if none is given.
+    pub fn new(host_or_ip: Option<&str>) -> Self {
+        let mut builder = WingConsole::builder();
+        if let Some(host) = host_or_ip {
+            builder = builder.host(host);
+        }
+        Self::from_builder(builder)
+    }
+
+    /// Creates a client that connects with the options set on `builder`.
+    pub fn from_builder(builder: WingConsoleBuilder) -> Self {
+        Self::with_connector(move || builder.connect())
+    }
+}
+
+impl<T: Transport> ReconnectingWingConsole<T> {
+    /// Creates a client that calls `connector` for each connection, for transports other than
+    /// TCP. An error from `connector` is a failed attempt, retried after a couple of seconds.
+    pub fn with_connector(connector: impl FnMut() -> Result<WingConsole<T>> + Send + 'static) -> Self {
+        Self {
+            connector: Box::new(connector),
+            console:   None,
+            retry_at:  Instant::now(),
+            watched:   BTreeSet::new(),
+            meters:    Vec::new(),
+            resyncing: 0,
+            events:    VecDeque::new(),
+        }
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.console.is_some()
+    }
+
+    /// The current connection, if there is one. Anything sent through it is lost if the
+    /// connection drops.
+    pub fn console(&mut self) -> Option<&mut WingConsole<T>> {
+        self.console.as_mut()
+    }
+
+    /// Adds `id` to the properties requested every time a connection is made, and requests it
+    /// now if connected.
+    pub fn watch(&mut self, id: i32) -> Result<()> {
+        if self.watched.insert(id) {
+            if let Some(console) = self.console.as_mut() {
+                console.request_node_data(id)?;
+                if self.resyncing > 0 {
+                    self.resyncing += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn unwatch(&mut self, id: i32) {
+        self.watched.remove(&id);
+    }
+
+    /// Subscribes to the meters of `sources`, like `WingConsole::subscribe_meters()`. The
+    /// subscription is sent again on every connection, until the `MeterStream` is dropped.
+    pub fn subscribe_meters(&mut self, sources: &[MeterSource]) -> Result<MeterStream> {
+        let (stream, subscription) = MeterStream::new(sources)?;
+        if let Some(console) = self.console.as_mut() {
+            console.add_meter_subscription(subscription.restart())?;
+        }
+        self.meters.push(subscription);
+        Ok(stream)
+    }
+
+    /// Returns the next response or connection event, connecting first if needed. While the
+    /// mixer can't be reached, this blocks retrying every couple of seconds.
+    ///
+    /// Losing the connection is not an error: it is returned as `ConnectionEvent::Lost`. Other
+    /// errors are returned as they are, such as `Error::Timeout` from a read timeout set on the
+    /// builder, and the connection is kept. To find out when the mixer stops answering, set a
+    /// `health_check()` on the builder: a stall counts as a lost connection.
+    pub fn read(&mut self) -> Result<WingEvent> {
+        loop {
+            if let Some(event) = self.next(None)? {
+                return Ok(event);
+            }
+        }
+    }
+
+    /// Like `read()`, but gives up after `timeout`, also while waiting to retry connecting. A
+    /// connection attempt that is due is still made, and blocks for as long as it takes, which
+    /// includes looking for a mixer if no host was given.
+    pub fn read_timeout(&mut self, timeout: Duration) -> Result<Option<WingEvent>> {
+        self.next(Instant::now().checked_add(timeout))
+    }
+
+    /// Like `read()`, but returns `None` instead of waiting for data or a retry. As with
+    /// `read_timeout()`, a connection attempt that is due still blocks.
+    pub fn try_read(&mut self) -> Result<Option<WingEvent>> {
+        self.read_timeout(Duration::ZERO)
+    }
+
+    fn next(&mut self, deadline: Option<Instant>) -> Result<Option<WingEvent>> {
+        loop {
+            if let Some(event) = self.events.pop_front() {
+                return Ok(Some(event));
+            }
+
+            let remaining = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+            let Some(console) = self.console.as_mut() else {
+                let wait = self.retry_at.saturating_duration_since(Instant::now());
+                if let Some(remaining) = remaining.filter(|r| *r < wait) {
+                    std::thread::sleep(remaining);
+                    return Ok(None);
+                }
+                std::thread::sleep(wait);
+                self.connect();
+                continue;
+            };
+
+            let result = match remaining {
+                Some(remaining) => console.read_timeout(remaining),
+                None => console.read().map(Some),
+            };
+            match result {
+                Ok(Some(response)) => {
+                    let end = matches!(response, WingResponse::RequestEnd);
+                    self.events.push_back(WingEvent::Response(response));
+                    if end && self.resyncing > 0 {
+                        self.resyncing -= 1;
+                        if self.resyncing == 0 {
+                            self.events.push_back(WingEvent::Connection(ConnectionEvent::Resynced));
+                        }
+                    }
+                }
+                Ok(None) => return Ok(None),
+                Err(Error::Io(_)) | Err(Error::ConnectionError) | Err(Error::Stalled(_)) => self.lose(),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Makes one connection attempt, queueing the events it causes.
+    fn connect(&mut self) {
+        self.events.push_back(WingEvent::Connection(ConnectionEvent::Connecting));
+        let mut console = match (self.connector)() {
+            Ok(console) => console,
+            Err(_) => {
+                self.events.push_back(WingEvent::Connection(ConnectionEvent::Failed));
+                self.retry_at = Instant::now() + RETRY_INTERVAL;
+                return;
+            }
+        };
+        self.events.push_back(WingEvent::Connection(ConnectionEvent::Connected));
+
+        self.meters.retain(|m| m.is_alive());
+        let sent = self.meters.iter().try_for_each(|m| console.add_meter_subscription(m.restart()))
+            .and_then(|_| self.watched.iter().try_for_each(|id| console.request_node_data(*id)));
+        if sent.is_err() {
+            self.lose();
+            return;
+        }
+        self.resyncing = self.watched.len();
+        if self.resyncing == 0 {
+            self.events.push_back(WingEvent::Connection(ConnectionEvent::Resynced));
+        }
+        self.console = Some(console);
+    }
+
+    fn lose(&mut self) {
+        self.console = None;
+        self.resyncing = 0;
+        self.events.push_back(WingEvent::Connection(ConnectionEvent::Lost));
+    }
+
+    fn connected(&mut self) -> Result<&mut WingConsole<T>> {
+        self.console.as_mut().ok_or(Error::ConnectionError)
+    }
+
+    pub fn request_node_definition(&mut self, id: i32) -> Result<()> {
+        self.connected()?.request_node_definition(id)
+    }
+
+    pub fn request_node_data(&mut self, id: i32) -> Result<()> {
+        self.connected()?.request_node_data(id)
+    }
+
+    pub fn set_string(&mut self, id: i32, value: &str) -> Result<()> {
+        self.connected()?.set_string(id, value)
+    }
+
+    pub fn set_float(&mut self, id: i32, value: f32) -> Result<()> {
+        self.connected()?.set_float(id, value)
+    }
+
+    pub fn set_int(&mut self, id: i32, value: i32) -> Result<()> {
+        self.connected()?.set_int(id, value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::codec::WingCodec;
+    use crate::testing::{pipe_console, pipe_console_with, PipeTransport};
+
+    // A client whose connections are pipes, pushed to the returned list as they are made. The
+    // first `failures` attempts fail.
+    fn client(mut failures: usize) -> (ReconnectingWingConsole<PipeTransport>, Arc<Mutex<Vec<PipeTransport>>>) {
+        let pipes = Arc::new(Mutex::new(Vec::new()));
+        let made = pipes.clone();
+        let wing = ReconnectingWingConsole::with_connector(move || {
+            if failures > 0 {
+                failures -= 1;
+                return Err(Error::ConnectionError);
+            }
+            let (console, pipe) = pipe_console();
+            made.lock().unwrap().push(pipe);
+            Ok(console)
+        });
+        (wing, pipes)
+    }
+
+    fn connection(event: WingEvent) -> ConnectionEvent {
+        match event {
+            WingEvent::Connection(event) => event,
+            WingEvent::Response(_) => panic!("expected a connection event"),
+        }
+    }
+
+    fn contains(sent: &[u8], bytes: &[u8]) -> bool {
+        sent.windows(bytes.len()).any(|w| w == bytes)
+    }
+
+    #[test]
+    fn reconnect_and_resync() {
+        let (mut wing, pipes) = client(0);
+        wing.watch(5).unwrap();
+        let mut request = Vec::new();
+        WingCodec::encode_request_node_data(5, &mut request).unwrap();
+
+        for _ in 0..2 {
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connected);
+            let pipe = pipes.lock().unwrap().last().unwrap().clone();
+            assert!(contains(&pipe.take_sent(), &request));
+
+            pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x02, 0xde]);
+            assert!(matches!(wing.read().unwrap(), WingEvent::Response(WingResponse::NodeData(_, 5, _))));
+            assert!(matches!(wing.read().unwrap(), WingEvent::Response(WingResponse::RequestEnd)));
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Resynced);
+            assert!(wing.is_connected());
+
+            pipe.hang_up();
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Lost);
+            assert!(!wing.is_connected());
+        }
+        assert_eq!(pipes.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn meters_are_resubscribed() {
+        let (mut wing, pipes) = client(0);
+        let stream = wing.subscribe_meters(&[crate::MeterSource::Channel(1)]).unwrap();
+        let request = wing.meters[0].request().to_vec();
+
+        for _ in 0..2 {
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connected);
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Resynced);
+            let pipe = pipes.lock().unwrap().last().unwrap().clone();
+            assert!(contains(&pipe.take_sent(), &request));
+            pipe.hang_up();
+            assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Lost);
+        }
+
+        // not once the stream is gone
+        drop(stream);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connected);
+        let pipe = pipes.lock().unwrap().last().unwrap().clone();
+        assert!(!contains(&pipe.take_sent(), &request));
+    }
+
+    #[test]
+    fn retry_wait_is_bounded() {
+        let (mut wing, pipes) = client(1);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Failed);
+
+        // the failed attempt didn't block, and waiting for the retry stops at the timeout
+        let start = Instant::now();
+        assert!(wing.read_timeout(Duration::from_millis(50)).unwrap().is_none());
+        assert!(wing.try_read().unwrap().is_none());
+        assert!(start.elapsed() < RETRY_INTERVAL);
+        assert!(pipes.lock().unwrap().is_empty());
+
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connected);
+        assert_eq!(pipes.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn timeouts_and_stalls() {
+        let mut wing = ReconnectingWingConsole::with_connector(|| {
+            Ok(pipe_console_with(Some(Duration::from_millis(20)), Some((Duration::from_secs(60), Duration::from_millis(50)))).0)
+        });
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connecting);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Connected);
+        assert_eq!(connection(wing.read().unwrap()), ConnectionEvent::Resynced);
+
+        // a mixer with nothing to say is still connected
+        assert!(matches!(wing.read(), Err(Error::Timeout)));
+        assert!(wing.is_connected());
+
+        // until the health check finds it stalled
+        let event = loop {
+            match wing.read() {
+                Err(Error::Timeout) => continue,
+                event => break event.unwrap(),
+            }
+        };
+        assert_eq!(connection(event), ConnectionEvent::Lost);
+    }
+}
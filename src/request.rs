@@ -0,0 +1,228 @@
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::{Result, Error, WingResponse};
+use crate::node::{WingNodeDef, WingNodeData};
+
+/// A request that has been sent to the mixer, which completes when the mixer's
+/// `RequestEnd` for it is read. Its reply holds just the responses that belong to it:
+/// `Option<WingNodeData>` for a data request, and `Vec<WingNodeDef>` for a definition request.
+///
+/// Replies are matched up by `read()`, so someone has to be reading. On the thread that reads,
+/// use `WingConsole::wait()`; from any other thread, use `wait_timeout()`. To check without
+/// waiting, use `is_done()`.
+pub struct PendingRequest<R> {
+    slot: Arc<Slot<R>>,
+}
+
+pub(crate) struct Slot<R> {
+    state: Mutex<SlotState<R>>,
+    done:  Condvar,
+}
+
+struct SlotState<R> {
+    reply:  R,
+    status: Status,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Status {
+    Pending,
+    Done,
+    Abandoned,
+}
+
+impl<R> Slot<R> {
+    fn lock(&self) -> MutexGuard<'_, SlotState<R>> {
+        crate::lock(&self.state)
+    }
+
+    fn finish(&self, status: Status) {
+        self.lock().status = status;
+        self.done.notify_all();
+    }
+}
+
+impl<R: Default> PendingRequest<R> {
+    pub(crate) fn new() -> Self {
+        Self {
+            slot: Arc::new(Slot {
+                state: Mutex::new(SlotState { reply: R::default(), status: Status::Pending }),
+                done:  Condvar::new(),
+            }),
+        }
+    }
+
+    /// True once the reply has arrived, or the connection it was sent on is gone.
+    pub fn is_done(&self) -> bool {
+        self.slot.lock().status != Status::Pending
+    }
+
+    /// Waits for another thread's `read()` to complete the request, and returns the reply.
+    /// Returns `Error::Timeout` if that doesn't happen within `timeout`, or
+    /// `Error::ConnectionError` if the connection is closed first.
+    pub fn wait_timeout(self, timeout: Duration) -> Result<R> {
+        // too long to have a deadline is the same as waiting forever
+        let deadline = Instant::now().checked_add(timeout);
+        let mut state = self.slot.lock();
+        while state.status == Status::Pending {
+            let Some(deadline) = deadline else {
+                state = self.slot.done.wait(state).unwrap_or_else(|e| e.into_inner());
+                continue;
+            };
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                return Err(Error::Timeout);
+            }
+            state = self.slot.done.wait_timeout(state, remaining).unwrap_or_else(|e| e.into_inner()).0;
+        }
+        match state.status {
+            Status::Abandoned => Err(Error::ConnectionError),
+            _ => Ok(std::mem::take(&mut state.reply)),
+        }
+    }
+}
+
+// A request the reader is waiting for a RequestEnd for, in the order they were sent.
+pub(crate) enum Outstanding {
+    // sent without a handle, so its responses and RequestEnd are passed on to read()
+    Untracked,
+    // a health check ping, whose answer is nobody's business
+    Ping,
+    Data(i32, Arc<Slot<Option<WingNodeData>>>),
+    Definition(i32, Arc<Slot<Vec<WingNodeDef>>>),
+}
+
+impl Outstanding {
+    pub(crate) fn data(id: i32) -> (Self, PendingRequest<Option<WingNodeData>>) {
+        let request = PendingRequest::new();
+        (Outstanding::Data(id, request.slot.clone()), request)
+    }
+
+    pub(crate) fn definition(id: i32) -> (Self, PendingRequest<Vec<WingNodeDef>>) {
+        let request = PendingRequest::new();
+        (Outstanding::Definition(id, request.slot.clone()), request)
+    }
+
+    // Gives `response` to this request if it belongs to it, otherwise hands it back.
+    pub(crate) fn offer(&mut self, response: WingResponse) -> Option<WingResponse> {
+        match (self, response) {
+            // a ping's answer is the current node's data, which nobody asked to see; for a
+            // container that is the data of each child
+            (Outstanding::Ping, _) => None,
+            (Outstanding::Data(id, slot), WingResponse::NodeData(_, data_id, data)) if data_id == *id => {
+                slot.lock().reply = Some(data);
+                None
+            }
+            (Outstanding::Definition(id, slot), WingResponse::NodeDef(def)) if def.id == *id || def.parent_id == *id => {
+                slot.lock().reply.push(def);
+                None
+            }
+            (_, response) => Some(response),
+        }
+    }
+
+    // Completes the request on its RequestEnd, returning true if that should be passed on.
+    pub(crate) fn end(self) -> bool {
+        match self {
+            Outstanding::Untracked => true,
+            Outstanding::Ping => false,
+            Outstanding::Data(_, slot) => { slot.finish(Status::Done); false }
+            Outstanding::Definition(_, slot) => { slot.finish(Status::Done); false }
+        }
+    }
+
+    // Wakes anyone waiting for a request that will now never complete.
+    pub(crate) fn abandon(self) {
+        match self {
+            Outstanding::Untracked | Outstanding::Ping => {}
+            Outstanding::Data(_, slot) => slot.finish(Status::Abandoned),
+            Outstanding::Definition(_, slot) => slot.finish(Status::Abandoned),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::testing::pipe_console;
+    use crate::{WingResponse, Error};
+
+    #[test]
+    fn pipelined_requests() {
+        let (mut wing, pipe) = pipe_console();
+
+        let first = wing.fetch_node_data(5).unwrap();
+        wing.request_node_data(6).unwrap();
+        let third = wing.fetch_node_data(7).unwrap();
+
+        pipe.receive(&[
+            0xdf, 0xd1,
+            0xd7, 0, 0, 0, 9, 0x01,         // unsolicited
+            0xd7, 0, 0, 0, 5, 0x02, 0xde,   // first
+            0xd7, 0, 0, 0, 6, 0x03, 0xde,   // untracked
+            0xd7, 0, 0, 0, 7, 0x04, 0xde,   // third
+        ]);
+
+        let data = wing.wait(third).unwrap().unwrap();
+        assert_eq!(data.get_int(), 4);
+        assert!(first.is_done());
+        assert_eq!(first.wait_timeout(Duration::ZERO).unwrap().unwrap().get_int(), 2);
+
+        // only what wasn't for a tracked request reaches read()
+        assert!(matches!(wing.try_read().unwrap(), Some(WingResponse::NodeData(_, 9, _))));
+        assert!(matches!(wing.try_read().unwrap(), Some(WingResponse::NodeData(_, 6, _))));
+        assert!(matches!(wing.try_read().unwrap(), Some(WingResponse::RequestEnd)));
+        assert!(wing.try_read().unwrap().is_none());
+    }
+
+    #[test]
+    fn get_errors() {
+        let (mut wing, pipe) = pipe_console();
+
+        pipe.receive(&[0xdf, 0xd1, 0xde]);
+        assert!(matches!(wing.get(5, Duration::from_secs(1)), Err(Error::NotFound(_))));
+        assert!(matches!(wing.get(5, Duration::from_millis(10)), Err(Error::Timeout)));
+
+        // the reply to the request that timed out doesn't turn up later
+        pipe.receive(&[0xd7, 0, 0, 0, 5, 0x02, 0xde]);
+        assert!(wing.try_read().unwrap().is_none());
+    }
+
+    #[test]
+    fn get_with_huge_timeout() {
+        let (mut wing, pipe) = pipe_console();
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x02, 0xde, 0xde]);
+        assert_eq!(wing.get(5, Duration::MAX).unwrap().get_int(), 2);
+        assert!(matches!(wing.get(6, Duration::MAX), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn refused_requests_are_not_matched() {
+        let (mut wing, pipe) = pipe_console();
+        pipe.pipe().write_limit = Some(0);
+        // fill the queue with messages as long as a request
+        while wing.set_int(1, 1).is_ok() {}
+        assert!(matches!(wing.fetch_node_data(5), Err(Error::QueueFull)));
+        assert!(matches!(wing.request_node_data(6), Err(Error::QueueFull)));
+
+        // once the queue drains, replies still go to the requests that were sent
+        pipe.pipe().write_limit = None;
+        let request = wing.fetch_node_data(7).unwrap();
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 7, 0x04, 0xde]);
+        assert_eq!(wing.wait_timeout(request, Duration::from_secs(1)).unwrap().unwrap().get_int(), 4);
+        assert!(wing.try_read().unwrap().is_none());
+    }
+
+    #[test]
+    fn wait_from_another_thread() {
+        let (mut wing, pipe) = pipe_console();
+        let request = wing.fetch_node_data(5).unwrap();
+        let waiting = std::thread::spawn(move || request.wait_timeout(Duration::MAX));
+
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x02, 0xde]);
+        assert!(wing.read_timeout(Duration::from_millis(100)).unwrap().is_none());
+        assert_eq!(waiting.join().unwrap().unwrap().unwrap().get_int(), 2);
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::console::{WingConsole, KEEP_ALIVE_INTERVAL};
+use crate::transport::Transport;
+
+// Both directions of an in-memory connection: what the mixer sent, and what we wrote.
+#[derive(Default)]
+pub(crate) struct Pipe {
+    pub(crate) rx:          VecDeque<u8>,
+    pub(crate) tx:          Vec<u8>,
+    // close() was called on our end
+    pub(crate) closed:      bool,
+    // the mixer's end is gone, so reads return 0 once rx is empty
+    pub(crate) hung_up:     bool,
+    // the most bytes one write takes; Some(0) makes writes time out
+    pub(crate) write_limit: Option<usize>,
+}
+
+// A `Transport` over a `Pipe`. Clones share the pipe, so a test keeps one to play the mixer.
+#[derive(Clone, Default)]
+pub(crate) struct PipeTransport(Arc<(Mutex<Pipe>, Condvar)>);
+
+impl PipeTransport {
+    pub(crate) fn pipe(&self) -> MutexGuard<'_, Pipe> {
+        self.0.0.lock().unwrap()
+    }
+
+    // Queues `bytes` as if the mixer had sent them, waking a blocked read.
+    pub(crate) fn receive(&self, bytes: &[u8]) {
+        self.pipe().rx.extend(bytes);
+        self.0.1.notify_all();
+    }
+
+    // Ends the mixer's side: reads return 0 once what was received has been read.
+    pub(crate) fn hang_up(&self) {
+        self.pipe().hung_up = true;
+        self.0.1.notify_all();
+    }
+
+    // Takes what has been written so far.
+    pub(crate) fn take_sent(&self) -> Vec<u8> {
+        std::mem::take(&mut self.pipe().tx)
+    }
+}
+
+// A console on a new pipe, and the pipe to play the mixer with.
+pub(crate) fn pipe_console() -> (WingConsole<PipeTransport>, PipeTransport) {
+    pipe_console_with(None, None)
+}
+
+// Like `pipe_console()`, with the read timeout and health check a builder would set.
+pub(crate) fn pipe_console_with(read_timeout: Option<Duration>, health_check: Option<(Duration, Duration)>)
+                                -> (WingConsole<PipeTransport>, PipeTransport) {
+    let pipe = PipeTransport::default();
+    let console = WingConsole::with_options(pipe.clone(), KEEP_ALIVE_INTERVAL, read_timeout, health_check).unwrap();
+    (console, pipe)
+}
+
+impl Transport for PipeTransport {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize> {
+        let deadline = Instant::now().checked_add(timeout);
+        let mut pipe = self.pipe();
+        while pipe.rx.is_empty() && !pipe.hung_up {
+            let remaining = deadline.map_or(Duration::MAX, |d| d.saturating_duration_since(Instant::now()));
+            if remaining.is_zero() {
+                return Err(std::io::ErrorKind::WouldBlock.into());
+            }
+            pipe = self.0.1.wait_timeout(pipe, remaining).unwrap().0;
+        }
+        let n = buf.len().min(pipe.rx.len());
+        for (i, b) in pipe.rx.drain(..n).enumerate() {
+            buf[i] = b;
+        }
+        Ok(n)
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut pipe = self.pipe();
+        let n = buf.len().min(pipe.write_limit.unwrap_or(usize::MAX));
+        if n == 0 && !buf.is_empty() {
+            return Err(std::io::ErrorKind::TimedOut.into());
+        }
+        pipe.tx.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.pipe().closed = true;
+        Ok(())
+    }
+
+    fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(self.clone())
+    }
+}
@@ -0,0 +1,138 @@
+use std::io::{Read, Write};
+use std::net::{Shutdown, TcpStream};
+use std::time::Duration;
+
+// How long a write may block before what's left of it is queued for later.
+const WRITE_TIMEOUT: Duration = Duration::from_millis(100);
+
+/// A connection that carries the native protocol's bytes, such as `TcpTransport`. Implement
+/// this to run a `WingConsole` over something else, with `WingConsole::from_transport()`.
+///
+/// A `WingConsole` reads and writes through a single handle, so a serial port or a replayed
+/// capture only needs `read()`, `write()` and `close()`. `try_clone()` is only needed to
+/// `split()` the console.
+pub trait Transport: Send + Sized + 'static {
+    /// Reads whatever is available into `buf`, waiting at most `timeout` for something to
+    /// arrive; a zero `timeout` means don't wait at all. Returns the number of bytes read, 0 once
+    /// the connection is closed, or an error of kind `WouldBlock` or `TimedOut` if nothing
+    /// arrived in time.
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize>;
+
+    /// Writes some of `buf`, returning how much was written. If nothing can be written for now
+    /// it may return `WouldBlock` or `TimedOut`, and the rest is tried again later.
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize>;
+
+    /// Closes the connection, for this handle and every clone of it.
+    fn close(&mut self) -> std::io::Result<()>;
+
+    /// Returns another handle to the same connection, so reading and writing can happen on
+    /// different threads. Only `WingConsole::split()` uses it; by default it returns an error of
+    /// kind `Unsupported`.
+    fn try_clone(&self) -> std::io::Result<Self> {
+        Err(std::io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// The native protocol over TCP, which is what `WingConsole::connect()` uses.
+pub struct TcpTransport {
+    stream: TcpStream,
+}
+
+impl TcpTransport {
+    pub fn new(stream: TcpStream) -> std::io::Result<Self> {
+        stream.set_nodelay(true)?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        Ok(Self { stream })
+    }
+}
+
+impl Transport for TcpTransport {
+    fn read(&mut self, buf: &mut [u8], timeout: Duration) -> std::io::Result<usize> {
+        // a zero read timeout isn't allowed, so just check what's there
+        if timeout.is_zero() {
+            self.stream.set_nonblocking(true)?;
+            let ret = self.stream.read(buf);
+            self.stream.set_nonblocking(false)?;
+            ret
+        } else {
+            self.stream.set_read_timeout(Some(timeout))?;
+            self.stream.read(buf)
+        }
+    }
+
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.stream.write(buf)
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.stream.shutdown(Shutdown::Both)
+    }
+
+    fn try_clone(&self) -> std::io::Result<Self> {
+        Ok(Self { stream: self.stream.try_clone()? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::pipe_console;
+    use crate::{WingConsole, WingResponse, Error};
+
+    #[test]
+    fn console_over_pipe() {
+        let (mut wing, pipe) = pipe_console();
+        assert_eq!(pipe.take_sent(), [0xdf, 0xd1]);
+
+        assert!(wing.try_read().unwrap().is_none());
+        pipe.receive(&[0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03]);
+        match wing.read_timeout(Duration::from_secs(1)).unwrap() {
+            Some(WingResponse::NodeData(1, 5, data)) => assert_eq!(data.get_int(), 3),
+            _ => panic!("expected the data of node 5"),
+        }
+
+        wing.set_int(7, 1).unwrap();
+        assert_eq!(pipe.take_sent(), [0xd7, 0, 0, 0, 7, 0x01]);
+
+        drop(wing);
+        assert!(pipe.pipe().closed);
+    }
+    // Plays back what a mixer once sent, and throws away what is written. It has no second
+    // handle to give, so it leaves out try_clone().
+    struct Replay {
+        capture: std::collections::VecDeque<u8>,
+    }
+
+    impl Transport for Replay {
+        fn read(&mut self, buf: &mut [u8], _timeout: Duration) -> std::io::Result<usize> {
+            let n = buf.len().min(self.capture.len());
+            for (i, b) in self.capture.drain(..n).enumerate() {
+                buf[i] = b;
+            }
+            Ok(n)
+        }
+
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn close(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn transport_without_clone() {
+        let replay = || Replay { capture: [0xdf, 0xd1, 0xd7, 0, 0, 0, 5, 0x03, 0xde].into() };
+
+        let mut wing = WingConsole::from_transport(replay()).unwrap();
+        wing.set_int(7, 1).unwrap();
+        assert!(matches!(wing.read().unwrap(), WingResponse::NodeData(1, 5, _)));
+        assert!(matches!(wing.read().unwrap(), WingResponse::RequestEnd));
+        // the end of the capture is the connection closing
+        assert!(matches!(wing.read(), Err(Error::ConnectionError)));
+
+        let wing = WingConsole::from_transport(replay()).unwrap();
+        assert!(matches!(wing.split(), Err(Error::Io(e)) if e.kind() == std::io::ErrorKind::Unsupported));
+    }
+}
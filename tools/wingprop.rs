@@ -3,7 +3,7 @@ use utils::Args;
 
 use std::result::Result;
 
-use libwing::{WingConsole, WingResponse, WingNodeDef, NodeType};
+use libwing::{WingConsole, NodeType};
 
 fn main() -> Result<(),libwing::Error> {
     let mut args = Args::new(r#"
@@ -98,13 +98,31 @@ Usage: wingprop [-h host] [-j] property[=value|?]
         };
 
     let mut wing = WingConsole::connect(host.as_deref())?;
-    
+    let timeout = std::time::Duration::from_secs(5);
+
     match action {
         Action::Lookup => {
             if proptype == NodeType::Node {
-                wing.request_node_definition(propid)?;
+                let children = wing.get_children_defs(propid, timeout).unwrap_or_else(|e| fail(&propname, e));
+                if jsonoutput {
+                    let mut ret = jzon::array![ ];
+                    for child in children {
+                        ret.push(child.to_json()).unwrap();
+                    }
+                    println!("{}", ret);
+                } else {
+                    for child in children {
+                        println!("{}", child.to_description());
+                        println!();
+                    }
+                }
             } else {
-                wing.request_node_data(propid)?;
+                let data = wing.get(propid, timeout).unwrap_or_else(|e| fail(&propname, e));
+                if jsonoutput {
+                    println!("{}", data.get_string());
+                } else {
+                    println!("{} = {}", propname, data.get_string());
+                }
             }
         },
         Action::Set(val) => {
@@ -113,11 +131,13 @@ Usage: wingprop [-h host] [-j] property[=value|?]
                     eprintln!("Can not set node {} because it's a node, and not a property.", propname);
                     std::process::exit(1);
                 },
+                NodeType::Unknown(_) => {
+                    eprintln!("Can not set property {} because its type is unknown.", propname);
+                    std::process::exit(1);
+                },
                 NodeType::StringEnum |
                 NodeType::String => {
                     wing.set_string(propid, &val)?;
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    std::process::exit(0);
                 },
                 NodeType::Integer => {
                     if let Ok(v) = val.parse::<i32>() {
@@ -125,8 +145,6 @@ Usage: wingprop [-h host] [-j] property[=value|?]
                     } else {
                         eprintln!("Property {} is an integer, but that was not passed: {}", propname, val);
                     }
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    std::process::exit(0);
                 },
                 NodeType::FloatEnum |
                 NodeType::FaderLevel |
@@ -137,81 +155,39 @@ Usage: wingprop [-h host] [-j] property[=value|?]
                     } else {
                         eprintln!("Property {} is a floating point number, but that was not passed: {}", propname, val);
                     }
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                    std::process::exit(0);
                 }
             }
+            std::thread::sleep(std::time::Duration::from_millis(100));
         },
         Action::Definition => {
-            if proptype == NodeType::Node {
-                wing.request_node_definition(propparentid)?;
-            } else {
-                wing.request_node_definition(propid)?;
+            let request =
+                if proptype == NodeType::Node {
+                    wing.fetch_node_definitions(propparentid)?
+                } else {
+                    wing.fetch_node_definitions(propid)?
+                };
+            let defs = wing.wait_timeout(request, timeout).unwrap_or_else(|e| fail(&propname, e));
+            for d in defs.into_iter().filter(|d| d.id == propid) {
+                if jsonoutput {
+                    let mut json = d.to_json();
+                    json.insert("fullname", propname.clone()).unwrap();
+                    println!("{}", json);
+                } else {
+                    println!("Property:  {}", propname);
+                    println!("{}", d.to_description());
+                    println!();
+                }
             }
         }
     }
+    Ok(())
+}
 
-    let mut children = Vec::<WingNodeDef>::new();
-
-    loop {
-        match wing.read()? {
-            WingResponse::RequestEnd => {
-                if !children.is_empty() {
-                    if jsonoutput {
-                        let mut ret = jzon::array![ ];
-                        for child in children {
-                            ret.push(child.to_json()).unwrap();
-                        }
-                        println!("{}", ret);
-
-                    } else {
-                        for child in children {
-                            println!("{}", child.to_description());
-                            println!();
-                        }
-                    }
-                }
-                std::process::exit(0);
-            },
-            WingResponse::NodeData(_, id, data) => {
-                if id == propid {
-                    match proptype {
-                        NodeType::Node => {
-                            eprintln!("printing node for {}", propname);
-                            std::process::exit(1);
-                        },
-                        NodeType::StringEnum |
-                        NodeType::Integer |
-                        NodeType::FloatEnum |
-                        NodeType::LinearFloat |
-                        NodeType::LogarithmicFloat |
-                        NodeType::FaderLevel |
-                        NodeType::String => {
-                            if jsonoutput {
-                                println!("{}", data.get_string());
-                            } else {
-                                println!("{} = {}", propname, data.get_string());
-                            }
-                        },
-                    }
-                }
-            },
-            WingResponse::NodeDef(d) => {
-                if d.id == propid && matches!(action, Action::Definition) {
-                    if jsonoutput {
-                        let mut json = d.to_json();
-                        json.insert("fullname", propname.clone()).unwrap();
-                        println!("{}", json);
-                    } else {
-                        println!("Property:  {}", propname);
-                        println!("{}", d.to_description());
-                        println!();
-                    }
-                }
-                if proptype == NodeType::Node && matches!(action, Action::Lookup) && d.parent_id == propid {
-                    children.push(d);
-                }
-            },
-        }
+fn fail(propname: &str, e: libwing::Error) -> ! {
+    match e {
+        libwing::Error::Timeout => eprintln!("No response from the mixer for {}", propname),
+        libwing::Error::NotFound(_) => eprintln!("The mixer does not have {}", propname),
+        e => eprintln!("Failed to get {}: {}", propname, e),
     }
+    std::process::exit(1);
 }
@@ -5,30 +5,17 @@ use std::fs::File;
 use std::io::Write;
 use std::result::Result;
 
-use libwing::{WingConsole, WingResponse, WingNodeDef};
+use libwing::{WingConsole, WingNodeDef};
 
 fn get_node_def(wing: &mut WingConsole, parents: Vec<i32>) -> Vec<Vec<WingNodeDef>> {
-    for parent in &parents {
-        wing.request_node_definition(*parent).unwrap();
-    }
+    let requests: Vec<_> = parents.iter()
+        .map(|parent| wing.fetch_node_definitions(*parent).unwrap())
+        .collect();
 
     let mut ret = Vec::new();
-    for parent in &parents {
-        let mut ret2 = Vec::new();
-        loop {
-            match wing.read().unwrap() {
-                WingResponse::NodeData(_,_,_) => { }
-                WingResponse::NodeDef(def) => {
-                    if def.parent_id == *parent {
-                        ret2.push(def);
-                    }
-                }
-                WingResponse::RequestEnd => {
-                    break;
-                }
-            }
-        }
-        ret.push(ret2);
+    for (parent, request) in parents.iter().zip(requests) {
+        let defs = wing.wait(request).unwrap();
+        ret.push(defs.into_iter().filter(|def| def.parent_id == *parent).collect());
     }
     ret
 }
@@ -56,7 +43,7 @@ fn add(cnt: usize, wing: &mut WingConsole, json_file: &mut File, raw: &mut Vec<u
                 raw.push(0);
                 for b in (fullname.len() as u16).to_be_bytes() { raw.push(b); }
                 for b in fullname.clone().into_bytes() { raw.push(b); }
-                for b in (def.raw.len() as u16).to_be_bytes() { raw.push(b); }
+                for b in (def.raw.len() as u32).to_be_bytes() { raw.push(b); }
                 raw.append(&mut def.raw.clone());
 
                 cnt = add(cnt, wing, json_file, raw, &fullname, &children[i], false);
@@ -93,7 +80,7 @@ fn add(cnt: usize, wing: &mut WingConsole, json_file: &mut File, raw: &mut Vec<u
             raw.push(if ignore { def.index as u8 } else { 0 });
             for b in (fullname.len() as u16).to_be_bytes() { raw.push(b); }
             for b in fullname.clone().into_bytes() { raw.push(b); }
-            for b in (def.raw.len() as u16).to_be_bytes() { raw.push(b); }
+            for b in (def.raw.len() as u32).to_be_bytes() { raw.push(b); }
             raw.append(&mut def.raw.clone());
 
             cnt = add(cnt, wing, json_file, raw, &fullname, &children[i], false);
@@ -172,11 +159,12 @@ Do you have a backup snapshot you can restore after, and want to continue?
     writeln!(rust_file, "            i += 2;").unwrap();
     writeln!(rust_file, "            let name = String::from_utf8(d[i..i + namelen].to_vec()).unwrap();").unwrap();
     writeln!(rust_file, "            i += namelen;").unwrap();
-    writeln!(rust_file, "            let deflen = u16::from_be_bytes([d[i], d[i + 1]]) as usize;").unwrap();
-    writeln!(rust_file, "            i += 2;").unwrap();
-    writeln!(rust_file, "            let def = WingNodeDef::from_bytes(&d[i..i + deflen]);").unwrap();
+    writeln!(rust_file, "            let deflen = u32::from_be_bytes([d[i], d[i + 1], d[i + 2], d[i + 3]]) as usize;").unwrap();
+    writeln!(rust_file, "            i += 4;").unwrap();
+    writeln!(rust_file, "            if let Ok(def) = WingNodeDef::from_bytes(&d[i..i + deflen]) {{").unwrap();
+    writeln!(rust_file, "                m.insert(name, def);").unwrap();
+    writeln!(rust_file, "            }}").unwrap();
     writeln!(rust_file, "            i += deflen;").unwrap();
-    writeln!(rust_file, "            m.insert(name, def);").unwrap();
     writeln!(rust_file, "        }}").unwrap();
     writeln!(rust_file, "        m").unwrap();
     writeln!(rust_file, "    }};").unwrap();